@@ -0,0 +1,274 @@
+use clap::ArgMatches;
+use codeforces_api::requests::CFAPIRequestable;
+use codeforces_api::responses::{CFResponse, CFResponseStatus, CFResult};
+use codeforces_api::Error as ApiError;
+use directories::ProjectDirs;
+use std::fs::{self, DirBuilder};
+use std::time::{Duration, SystemTime};
+
+use crate::handlers::{
+    exit_with_error, get_api_key_secret, get_optional_arg_of_type,
+};
+use crate::{cache, config};
+
+const DEFAULT_RETRIES: u32 = 3;
+/// Codeforces allows roughly one API call every two seconds.
+const DEFAULT_MIN_INTERVAL: f64 = 2.0;
+const MAX_BACKOFF: f64 = 60.0;
+
+/// Settings which control how requests are spaced out and retried.
+pub struct RequestSettings {
+    pub retries: u32,
+    pub min_interval: Duration,
+}
+
+impl RequestSettings {
+    /// Read settings from the command line, falling back to config.yml and
+    /// then to the defaults.
+    pub fn from_args(args: &ArgMatches) -> RequestSettings {
+        let conf = config::get_config_or_default();
+        let retries = get_optional_arg_of_type(args, "retries")
+            .or(conf.retries)
+            .unwrap_or(DEFAULT_RETRIES);
+        let min_interval = get_optional_arg_of_type(args, "mininterval")
+            .or(conf.min_interval)
+            .unwrap_or(DEFAULT_MIN_INTERVAL);
+        if !min_interval.is_finite() || min_interval < 0.0 {
+            exit_with_error("min interval must be a non-negative number");
+        }
+        RequestSettings {
+            retries,
+            min_interval: Duration::from_secs_f64(min_interval),
+        }
+    }
+}
+
+/// Send a request (or fetch it from the cache), returning the undecoded
+/// response. Exits if in offline mode and the response isn't cached.
+///
+/// Requests are spaced out by at least the minimum interval (across
+/// invocations of caffeine) and transient failures are retried with
+/// exponential backoff.
+pub fn request_raw<T: CFAPIRequestable>(
+    args: &ArgMatches,
+    x: &T,
+) -> Result<String, ApiError> {
+    let mode = cache::CacheMode::from_args(args);
+    let params = x.query_params();
+    let (k, s) = get_api_key_secret(args);
+    if let Some(s) = cache::get(&mode, x.method_name(), &params, &k) {
+        return Ok(s);
+    }
+    if mode.offline {
+        exit_with_error(format!(
+            "no cached response for {} (offline mode)",
+            x.method_name()
+        ));
+    }
+    let settings = RequestSettings::from_args(args);
+
+    let mut attempt = 0;
+    loop {
+        wait_for_rate_limit(settings.min_interval);
+        let res = x.get_raw(&k, &s).and_then(check_transient);
+        match res {
+            Err(ref e) if attempt < settings.retries && is_transient(e) => {
+                attempt += 1;
+                let delay = backoff(&settings, attempt);
+                eprintln!(
+                    "request failed ({}), retrying in {:.1} seconds \
+                    (attempt {}/{})",
+                    e,
+                    delay.as_secs_f64(),
+                    attempt,
+                    settings.retries
+                );
+                std::thread::sleep(delay);
+            }
+            Ok(raw) => {
+                cache::put(x.method_name(), &params, &k, &raw);
+                return Ok(raw);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Analogous to `request_raw()`, but decodes the response.
+pub fn request<T: CFAPIRequestable>(
+    args: &ArgMatches,
+    x: &T,
+) -> Result<CFResult, ApiError> {
+    let raw = request_raw(args, x)?;
+    match serde_json::from_str::<CFResponse>(&raw) {
+        Ok(CFResponse {
+            status: CFResponseStatus::Ok,
+            result: Some(res),
+            ..
+        }) => Ok(res),
+        Ok(CFResponse { comment, .. }) => Err(ApiError::CodeforcesApi(
+            comment.unwrap_or_else(|| "unknown error".to_string()),
+        )),
+        Err(e) => exit_with_error(format!("Parse: {}", e)),
+    }
+}
+
+/// Raw responses are returned regardless of their HTTP status code, so
+/// failures which should be retried are detected from the response body.
+fn check_transient(raw: String) -> Result<String, ApiError> {
+    if !raw.trim_start().starts_with('{') {
+        // Codeforces returns an HTML error page for 5xx errors.
+        let first_line = raw.trim().lines().next().unwrap_or_default();
+        Err(ApiError::CodeforcesApi(format!(
+            "unexpected response from server: {}",
+            first_line.chars().take(80).collect::<String>()
+        )))
+    } else if raw.starts_with("{\"status\":\"FAILED\"")
+        && raw.contains("Call limit exceeded")
+    {
+        Err(ApiError::CodeforcesApi("Call limit exceeded".to_string()))
+    } else {
+        Ok(raw)
+    }
+}
+
+fn is_transient(e: &ApiError) -> bool {
+    match e {
+        ApiError::Http(e) => {
+            e.is_timeout()
+                || e.is_connect()
+                || e.status().is_some_and(|s| s.is_server_error())
+        }
+        ApiError::CodeforcesApi(s) => {
+            s.starts_with("unexpected response from server")
+                || s.contains("Call limit exceeded")
+        }
+        _ => false,
+    }
+}
+
+fn backoff(settings: &RequestSettings, attempt: u32) -> Duration {
+    let base = settings.min_interval.as_secs_f64().max(1.0);
+    let delay = base * 2f64.powi(attempt as i32 - 1);
+    Duration::from_secs_f64(delay.min(MAX_BACKOFF))
+}
+
+/// Sleep until at least `min_interval` has passed since the last request.
+/// The time of the last request is stored in a file in the cache dir so that
+/// calls are also spaced out across separate invocations (eg. from scripts).
+fn wait_for_rate_limit(min_interval: Duration) {
+    let proj_dirs = match ProjectDirs::from(
+        crate::NAME_QUL,
+        crate::NAME_ORG,
+        crate::NAME_BIN,
+    ) {
+        Some(p) => p,
+        None => return,
+    };
+    let path = proj_dirs.cache_dir().join(crate::RATE_LIMIT_FILE_NAME);
+    let last = fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(|ms| SystemTime::UNIX_EPOCH + Duration::from_millis(ms));
+    if let Some(elapsed) =
+        last.and_then(|t| SystemTime::now().duration_since(t).ok())
+    {
+        if elapsed < min_interval {
+            std::thread::sleep(min_interval - elapsed);
+        }
+    }
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    if DirBuilder::new()
+        .recursive(true)
+        .create(proj_dirs.cache_dir())
+        .is_ok()
+    {
+        let _ = fs::write(&path, now.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAILED: &str = "{\"status\":\"FAILED\",\"comment\":";
+
+    #[test]
+    fn detects_transient_failures() {
+        let html = "<html>\n<head><title>502 Bad Gateway</title></head>\n\
+            <body>nginx</body>\n</html>";
+        let limit = format!("{}\"Call limit exceeded\"}}", FAILED);
+        let not_found =
+            format!("{}\"handles: User with handle x not found\"}}", FAILED);
+        let ok = "{\"status\":\"OK\",\"result\":[]}";
+        for (raw, expected) in [
+            (
+                html,
+                Err("unexpected response from server: <html>".to_string()),
+            ),
+            (&limit, Err("Call limit exceeded".to_string())),
+            (&not_found, Ok(())),
+            (ok, Ok(())),
+        ] {
+            let res = check_transient(raw.to_string());
+            match (&res, &expected) {
+                (Ok(r), Ok(())) => assert_eq!(r, raw),
+                (Err(e @ ApiError::CodeforcesApi(s)), Err(msg)) => {
+                    assert_eq!(s, msg);
+                    assert!(is_transient(e), "{}", raw);
+                }
+                _ => panic!("{}: {:?}", raw, res.map_err(|e| e.to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn only_retries_transient_errors() {
+        for (e, expected) in [
+            ("unexpected response from server: <html>", true),
+            ("Call limit exceeded", true),
+            ("handles: User with handle x not found", false),
+            ("contestId: Contest with id 9999 not found", false),
+        ] {
+            let e = ApiError::CodeforcesApi(e.to_string());
+            assert_eq!(is_transient(&e), expected, "{}", e);
+        }
+        // nothing listens on port 1, so connecting fails
+        let e = reqwest::blocking::get("http://127.0.0.1:1/").unwrap_err();
+        assert!(is_transient(&ApiError::Http(e)));
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let secs = |min_interval: f64, attempt| {
+            let settings = RequestSettings {
+                retries: 10,
+                min_interval: Duration::from_secs_f64(min_interval),
+            };
+            backoff(&settings, attempt).as_secs_f64()
+        };
+        for (min_interval, attempt, expected) in [
+            (2.0, 1, 2.0),
+            (2.0, 2, 4.0),
+            (2.0, 3, 8.0),
+            (2.0, 5, 32.0),
+            (2.0, 6, MAX_BACKOFF),
+            (2.0, 20, MAX_BACKOFF),
+            // the interval is at least a second
+            (0.0, 1, 1.0),
+            (0.25, 2, 2.0),
+            (0.0, 7, MAX_BACKOFF),
+            (100.0, 1, MAX_BACKOFF),
+        ] {
+            assert_eq!(
+                secs(min_interval, attempt),
+                expected,
+                "{} {}",
+                min_interval,
+                attempt
+            );
+        }
+    }
+}
@@ -1,54 +1,120 @@
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use std::fs::{DirBuilder, File, OpenOptions};
+use std::fs::{File, OpenOptions};
 use std::io::{ErrorKind, Read, Result as IoResult, Write};
+use std::sync::Mutex;
+
+use crate::crypto::{Key, Sealed};
+use crate::{config, permissions, secrets};
+
+/// Environment variable holding the key which unlocks an encrypted auth.yml
+/// (as printed by `caffeine unlock`), so that the passphrase is only entered
+/// once per shell session.
+pub const AUTH_KEY_ENV: &str = "CAFFEINE_AUTH_KEY";
+
+/// Environment variables providing credentials, which are used rather than
+/// those in auth.yml (but not rather than those given as flags).
+pub const API_KEY_ENV: &str = "CAFFEINE_API_KEY";
+pub const API_SECRET_ENV: &str = "CAFFEINE_API_SECRET";
+pub const HANDLE_ENV: &str = "CAFFEINE_HANDLE";
+pub const PASSWORD_ENV: &str = "CAFFEINE_PASSWORD";
+
+/// Key (exported) which unlocked auth.yml earlier on, so that the passphrase
+/// is only asked for once per process.
+static UNLOCKED_KEY: Mutex<Option<String>> = Mutex::new(None);
 
 #[derive(Serialize, Deserialize)]
 struct Auth {
     api: Option<APIAuth>,
     login: Option<LoginAuth>,
+    /// The rest of the file, encrypted by `caffeine login --encrypt`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted: Option<Sealed>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct APIAuth {
     key: Option<String>,
     secret: Option<String>,
+    /// Secret store holding the secret (when it isn't stored in auth.yml).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret_store: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct LoginAuth {
     handle_or_email: Option<String>,
     password: Option<String>,
+    /// Secret store holding the password (when it isn't stored in auth.yml).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password_store: Option<String>,
 }
 
-pub fn get_api_key_secret_from_file() -> Result<(String, String), &'static str>
-{
-    match get_auth_creds_from_file() {
-        Ok(Auth { api, .. }) => match api {
-            Some(APIAuth {
-                key: Some(k),
-                secret: Some(s),
-            }) => Ok((k, s)),
-            _ => Err("missing api credentials in auth.yml"),
-        },
-        Err(e) => Err(e),
+/// Accounts which secrets are stored under in a secret store.
+fn api_secret_account(key: &str) -> String {
+    format!("api-secret:{}", key)
+}
+
+fn password_account(handle: &str) -> String {
+    format!("password:{}", handle)
+}
+
+/// Look up a secret which auth.yml refers to.
+fn resolve_secret(store: &str, account: &str) -> Result<String, String> {
+    let conf = config::get_config_or_default();
+    match secrets::open(store, &conf)?.get(account)? {
+        Some(s) => Ok(s),
+        None => Err(format!(
+            "{} not found in secret store ({}), use `caffeine login` to set \
+            it again",
+            account, store
+        )),
     }
 }
 
-pub fn get_login_details_from_file() -> Result<(String, String), &'static str> {
-    match get_auth_creds_from_file() {
-        Ok(Auth { login, .. }) => match login {
-            Some(LoginAuth {
-                handle_or_email: Some(h),
-                password: Some(p),
-            }) => Ok((h, p)),
-            _ => Err("missing login credentials in auth.yml"),
-        },
-        Err(e) => Err(e),
+pub fn get_api_key_secret_from_file() -> Result<(String, String), String> {
+    check_auth_file()?;
+    let (Auth { api, .. }, _) = unlock(get_auth_creds_from_file()?)?;
+    match api {
+        Some(APIAuth {
+            key: Some(k),
+            secret: Some(s),
+            ..
+        }) => Ok((k, s)),
+        Some(APIAuth {
+            key: Some(k),
+            secret: None,
+            secret_store: Some(store),
+        }) => {
+            let s = resolve_secret(&store, &api_secret_account(&k))?;
+            Ok((k, s))
+        }
+        _ => Err("missing api credentials in auth.yml".into()),
     }
 }
 
-fn get_auth_creds_from_file() -> Result<Auth, &'static str> {
+pub fn get_login_details_from_file() -> Result<(String, String), String> {
+    check_auth_file()?;
+    let (Auth { login, .. }, _) = unlock(get_auth_creds_from_file()?)?;
+    match login {
+        Some(LoginAuth {
+            handle_or_email: Some(h),
+            password: Some(p),
+            ..
+        }) => Ok((h, p)),
+        Some(LoginAuth {
+            handle_or_email: Some(h),
+            password: None,
+            password_store: Some(store),
+        }) => {
+            let p = resolve_secret(&store, &password_account(&h))?;
+            Ok((h, p))
+        }
+        _ => Err("missing login credentials in auth.yml".into()),
+    }
+}
+
+fn get_auth_creds_from_file() -> Result<Auth, String> {
     match ProjectDirs::from(crate::NAME_QUL, crate::NAME_ORG, crate::NAME_BIN) {
         Some(proj_dirs) => {
             let f = open_auth_file(proj_dirs, false);
@@ -59,59 +125,191 @@ fn get_auth_creds_from_file() -> Result<Auth, &'static str> {
                     match res {
                         Err(e) => match e.kind() {
                             ErrorKind::Interrupted => Err(
-                                "reading from auth.yml file was interrupted",
+                                "reading from auth.yml file was interrupted"
+                                    .into(),
                             ),
                             _ => {
-                                Err("reading from auth.yml failed unexpectedly")
+                                Err("reading from auth.yml failed unexpectedly"
+                                    .into())
                             }
                         },
                         Ok(_) => match serde_yaml::from_str::<Auth>(&s) {
                             Ok(a) => Ok(a),
-                            Err(_) => Err("failed to parse auth.yml file"),
+                            Err(_) => {
+                                Err("failed to parse auth.yml file".into())
+                            }
                         },
                     }
                 }
                 Err(e) => match e.kind() {
                     ErrorKind::NotFound => {
                         Err("(auth.yml not found) use `caffeine login` to setup
-                        API keys and/or login details.")
+                        API keys and/or login details."
+                            .into())
                     }
                     ErrorKind::PermissionDenied => {
-                        Err("could not open auth.yml, permission denied")
+                        Err("could not open auth.yml, permission denied".into())
                     }
-                    _ => Err("could not open auth.yml, unknown reason"),
+                    _ => Err("could not open auth.yml, unknown reason".into()),
                 },
             }
         }
-        None => Err("couldn't find a valid path to store keys"),
+        None => Err("couldn't find a valid path to store keys".into()),
+    }
+}
+
+/// Warn when auth.yml can be accessed by other users (or refuse to use it
+/// with `strict_permissions: true` in config.yml).
+fn check_auth_file() -> Result<(), String> {
+    let path = match ProjectDirs::from(
+        crate::NAME_QUL,
+        crate::NAME_ORG,
+        crate::NAME_BIN,
+    ) {
+        Some(proj_dirs) => proj_dirs.data_dir().join(crate::AUTH_FILE_NAME),
+        None => return Ok(()),
+    };
+    let problems = permissions::check_private(&path, false);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let conf = config::get_config_or_default();
+    if conf.strict_permissions.unwrap_or(false) {
+        Err(format!(
+            "refusing to use {} since it {} (see `caffeine doctor`)",
+            crate::AUTH_FILE_NAME,
+            problems.join(", ")
+        ))
+    } else {
+        eprintln!(
+            "warning: {} {} (see `caffeine doctor`)",
+            crate::AUTH_FILE_NAME,
+            problems.join(", ")
+        );
+        Ok(())
     }
 }
 
+/// Decrypt an encrypted auth.yml using the key in [`AUTH_KEY_ENV`], else by
+/// prompting for its passphrase. Returns the decrypted contents and the key
+/// used (None if the file wasn't encrypted).
+fn unlock(auth: Auth) -> Result<(Auth, Option<Key>), String> {
+    let sealed = match auth.encrypted {
+        Some(ref s) => s,
+        None => return Ok((auth, None)),
+    };
+    let decrypt = |key: &Key| -> Result<Auth, String> {
+        let plaintext = key.open(sealed)?;
+        serde_yaml::from_slice(&plaintext)
+            .map_err(|_| "failed to parse decrypted auth.yml file".into())
+    };
+    let unlocked = UNLOCKED_KEY
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    if let Some(key) = unlocked.and_then(|k| Key::import(&k, sealed).ok()) {
+        if let Ok(a) = decrypt(&key) {
+            return Ok((a, Some(key)));
+        }
+    }
+    if let Ok(exported) = std::env::var(AUTH_KEY_ENV) {
+        match Key::import(&exported, sealed) {
+            Ok(key) => match decrypt(&key) {
+                Ok(a) => return Ok((a, Some(key))),
+                Err(_) => eprintln!("{} doesn't unlock auth.yml", AUTH_KEY_ENV),
+            },
+            Err(e) => eprintln!("{}: {}", AUTH_KEY_ENV, e),
+        }
+    }
+    let passphrase = rpassword::prompt_password("passphrase for auth.yml: ")
+        .map_err(|e| {
+            format!(
+                "auth.yml is encrypted and the passphrase couldn't be read \
+                ({}), set {} with `caffeine unlock`",
+                e, AUTH_KEY_ENV
+            )
+        })?;
+    let key = Key::for_sealed(&passphrase, sealed)?;
+    let a = decrypt(&key)?;
+    *UNLOCKED_KEY.lock().unwrap_or_else(|e| e.into_inner()) =
+        Some(key.export());
+    Ok((a, Some(key)))
+}
+
+/// The key unlocking auth.yml (to be exported as [`AUTH_KEY_ENV`]), or None
+/// if it isn't encrypted.
+pub fn export_key() -> Result<Option<String>, String> {
+    check_auth_file()?;
+    let (_, key) = unlock(get_auth_creds_from_file()?)?;
+    Ok(key.map(|k| k.export()))
+}
+
+/// Move a secret into the secret store configured in config.yml (if any),
+/// returning the secret to keep in auth.yml and the name of the store.
+fn store_secret(
+    account: &str,
+    secret: Option<String>,
+) -> Result<(Option<String>, Option<String>), String> {
+    let conf = config::get_config_or_default();
+    match (secret, conf.secret_store.as_deref()) {
+        (Some(s), Some(name)) => {
+            let store = secrets::open(name, &conf)?;
+            store.set(account, &s)?;
+            Ok((None, Some(store.name().to_string())))
+        }
+        (s, _) => Ok((s, None)),
+    }
+}
+
+/// Store credentials in auth.yml, keeping any which aren't given. With a
+/// `passphrase`, the file is encrypted with a key derived from it, otherwise
+/// an already encrypted file stays encrypted with the same key.
 pub fn set_auth_creds(
     api_key: Option<String>,
     api_secret: Option<String>,
     login_handle: Option<String>,
     login_password: Option<String>,
-) -> Result<(), &'static str> {
+    passphrase: Option<String>,
+) -> Result<(), String> {
     match ProjectDirs::from(crate::NAME_QUL, crate::NAME_ORG, crate::NAME_BIN) {
         Some(proj_dirs) => {
+            check_auth_file()?;
             let mut x = Auth {
                 api: None,
                 login: None,
+                encrypted: None,
             };
             if api_key.is_some() || api_secret.is_some() {
+                let account =
+                    api_secret_account(api_key.as_deref().unwrap_or_default());
+                let (secret, secret_store) =
+                    store_secret(&account, api_secret)?;
                 x.api = Some(APIAuth {
                     key: api_key,
-                    secret: api_secret,
+                    secret,
+                    secret_store,
                 });
             }
             if login_handle.is_some() || login_password.is_some() {
+                let account = password_account(
+                    login_handle.as_deref().unwrap_or_default(),
+                );
+                let (password, password_store) =
+                    store_secret(&account, login_password)?;
                 x.login = Some(LoginAuth {
                     handle_or_email: login_handle,
-                    password: login_password,
+                    password,
+                    password_store,
                 });
             }
+            let mut key = None;
             if let Ok(a) = get_auth_creds_from_file() {
+                // The stored details can only be kept if they can be
+                // decrypted
+                let (a, k) = unlock(a).map_err(|e| {
+                    format!("{} (remove auth.yml to start over)", e)
+                })?;
+                key = k;
                 // If no details provided then use those already stored
                 if x.api.is_none() {
                     x.api = a.api;
@@ -123,6 +321,20 @@ pub fn set_auth_creds(
             // If auth file not successfully opened/parsed, then ignore
             // and overwrite all
 
+            if let Some(p) = passphrase {
+                key = Some(Key::new(&p)?);
+            }
+            if let Some(k) = key {
+                // unwrap is probably ok here since serializing errors are
+                // very rare
+                let plaintext = serde_yaml::to_string(&x).unwrap();
+                x = Auth {
+                    api: None,
+                    login: None,
+                    encrypted: Some(k.seal(plaintext.as_bytes())?),
+                };
+            }
+
             // unwrap is probably ok here since serializing errors are very rare
             let s = serde_yaml::to_string(&x).unwrap();
 
@@ -138,38 +350,116 @@ pub fn set_auth_creds(
                     match res {
                         Err(e) => match e.kind() {
                             ErrorKind::Interrupted => {
-                                Err("writing to auth.yml file was interrupted")
+                                Err("writing to auth.yml file was interrupted"
+                                    .into())
                             }
-                            _ => Err("writing to auth.yml failed unexpectedly"),
+                            _ => Err("writing to auth.yml failed unexpectedly"
+                                .into()),
                         },
                         Ok(_) => Ok(()),
                     }
                 }
                 Err(e) => match e.kind() {
                     ErrorKind::NotFound => Err(
-                        "could not open auth.yml, parent dir does not exist",
+                        "could not open auth.yml, parent dir does not exist"
+                            .into(),
                     ),
                     ErrorKind::PermissionDenied => {
-                        Err("could not open auth.yml, permission denied")
+                        Err("could not open auth.yml, permission denied".into())
                     }
-                    _ => Err("could not open auth.yml, unknown reason"),
+                    _ => Err("could not open auth.yml, unknown reason".into()),
                 },
             }
         }
-        None => Err("couldn't find a valid path to store keys"),
+        None => Err("couldn't find a valid path to store keys".into()),
     }
 }
 
+/// auth.yml (and the data dir) are created so that only the user can access
+/// them.
 fn open_auth_file(proj_dirs: ProjectDirs, write: bool) -> IoResult<File> {
-    let d = DirBuilder::new()
-        .recursive(true)
-        .create(proj_dirs.data_dir());
-    match d {
-        Ok(_) => OpenOptions::new()
-            .create(write)
-            .write(write)
-            .read(true)
-            .open(proj_dirs.data_dir().join(crate::AUTH_FILE_NAME)),
+    let path = proj_dirs.data_dir().join(crate::AUTH_FILE_NAME);
+    if write {
+        return permissions::open_private_file(&path, false);
+    }
+    match permissions::create_private_dir(proj_dirs.data_dir()) {
+        Ok(_) => OpenOptions::new().read(true).open(path),
         Err(e) => Err(e),
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    fn read_auth_yml() -> String {
+        let path = testutil::proj_dirs().data_dir().join(crate::AUTH_FILE_NAME);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn secret_store_keeps_only_references() {
+        let _dirs = testutil::isolated_dirs();
+        let stub = testutil::secret_tool_stub();
+        testutil::write_config(&format!(
+            "secret_store: secret-service\nsecret_tool: {}\n",
+            stub.display()
+        ));
+        set_auth_creds(
+            Some("abc".into()),
+            Some("s3cret".into()),
+            Some("thud".into()),
+            Some("p@ss".into()),
+            None,
+        )
+        .unwrap();
+
+        let s = read_auth_yml();
+        assert!(!s.contains("s3cret") && !s.contains("p@ss"), "{}", s);
+        let auth: Auth = serde_yaml::from_str(&s).unwrap();
+        let api = auth.api.unwrap();
+        assert_eq!(api.key.as_deref(), Some("abc"));
+        assert_eq!(api.secret, None);
+        assert_eq!(api.secret_store.as_deref(), Some("secret-service"));
+        let login = auth.login.unwrap();
+        assert_eq!(login.handle_or_email.as_deref(), Some("thud"));
+        assert_eq!(login.password, None);
+        assert_eq!(login.password_store.as_deref(), Some("secret-service"));
+
+        assert_eq!(
+            get_api_key_secret_from_file().unwrap(),
+            ("abc".to_string(), "s3cret".to_string())
+        );
+        assert_eq!(
+            get_login_details_from_file().unwrap(),
+            ("thud".to_string(), "p@ss".to_string())
+        );
+    }
+
+    #[test]
+    fn without_secret_store_keeps_secrets_in_auth_yml() {
+        let _dirs = testutil::isolated_dirs();
+        set_auth_creds(
+            None,
+            None,
+            Some("thud".into()),
+            Some("p@ss".into()),
+            None,
+        )
+        .unwrap();
+        let auth: Auth = serde_yaml::from_str(&read_auth_yml()).unwrap();
+        let login = auth.login.unwrap();
+        assert_eq!(login.password.as_deref(), Some("p@ss"));
+        assert_eq!(login.password_store, None);
+    }
+
+    #[test]
+    fn missing_secret_in_store() {
+        let _dirs = testutil::isolated_dirs();
+        let stub = testutil::secret_tool_stub();
+        testutil::write_config(&format!("secret_tool: {}\n", stub.display()));
+        let e = resolve_secret("secret-service", "password:thud").unwrap_err();
+        assert!(e.contains("password:thud"), "{}", e);
+    }
+}
@@ -0,0 +1,242 @@
+use clap::ArgMatches;
+use directories::ProjectDirs;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, DirBuilder};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::config;
+
+/// Default number of seconds for which responses to each API method are
+/// considered fresh. Methods which aren't listed are always refetched (unless
+/// in offline mode), though their responses are still stored.
+const DEFAULT_TTLS: &[(&str, u64)] = &[
+    ("contest.list", 60 * 60),
+    ("problemset.problems", 6 * 60 * 60),
+    ("user.ratedList", 60 * 60),
+    ("blogEntry.view", 10 * 60),
+    ("blogEntry.comments", 10 * 60),
+    ("contest.ratingChanges", 60 * 60),
+    ("user.info", 5 * 60),
+    ("user.rating", 5 * 60),
+];
+
+/// Settings which control how the cache is used for a single invocation.
+pub struct CacheMode {
+    /// Ignore cached responses (fresh responses are still stored).
+    pub no_cache: bool,
+    /// Never touch the network, only serve responses from the cache.
+    pub offline: bool,
+}
+
+impl CacheMode {
+    pub fn from_args(args: &ArgMatches) -> CacheMode {
+        CacheMode {
+            no_cache: args.is_present("nocache"),
+            offline: args.is_present("offline"),
+        }
+    }
+}
+
+/// Summary of the cache contents returned by [`stats`].
+pub struct CacheStats {
+    pub dir: PathBuf,
+    pub entries: usize,
+    pub bytes: u64,
+    /// Number of entries and bytes per API method.
+    pub methods: BTreeMap<String, (usize, u64)>,
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    ProjectDirs::from(crate::NAME_QUL, crate::NAME_ORG, crate::NAME_BIN)
+        .map(|proj_dirs| proj_dirs.cache_dir().join(crate::CACHE_API_DIR_NAME))
+}
+
+/// Path of the cache entry for a request. The query params are hashed since
+/// they can be arbitrarily long (eg. lists of handles). The API key the
+/// request is signed with is hashed along with them, since responses depend on
+/// the user (eg. `user.friends` or private contests).
+fn entry_path(
+    method: &str,
+    params: &[(&'static str, String)],
+    api_key: &str,
+) -> Option<PathBuf> {
+    let mut params = params.to_vec();
+    params.push(("apiKey", api_key.to_string()));
+    params.sort();
+    let mut hasher = Sha256::new();
+    for (key, val) in params {
+        hasher.update(key);
+        hasher.update("=");
+        hasher.update(val);
+        hasher.update("&");
+    }
+    let name = format!("{}-{}.json", method, hex::encode(hasher.finalize()));
+    cache_dir().map(|d| d.join(name))
+}
+
+/// Number of seconds for which responses to `method` are considered fresh,
+/// taking `cache_ttl` in config.yml into account.
+pub fn ttl(method: &str) -> Duration {
+    let configured = config::get_config_or_default()
+        .cache_ttl
+        .and_then(|m| m.get(method).copied());
+    let secs = configured.unwrap_or_else(|| {
+        DEFAULT_TTLS
+            .iter()
+            .find(|(m, _)| *m == method)
+            .map_or(0, |(_, t)| *t)
+    });
+    Duration::from_secs(secs)
+}
+
+/// Look up a cached raw response. In offline mode any stored response is
+/// returned regardless of its age.
+pub fn get(
+    mode: &CacheMode,
+    method: &str,
+    params: &[(&'static str, String)],
+    api_key: &str,
+) -> Option<String> {
+    if mode.no_cache && !mode.offline {
+        return None;
+    }
+    let path = entry_path(method, params, api_key)?;
+    if !mode.offline {
+        let age = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| SystemTime::now().duration_since(t).ok())?;
+        if age >= ttl(method) {
+            return None;
+        }
+    }
+    fs::read_to_string(path).ok()
+}
+
+/// Store a raw response. Only successful responses are stored. Failing to
+/// write to the cache is not considered an error.
+pub fn put(
+    method: &str,
+    params: &[(&'static str, String)],
+    api_key: &str,
+    raw: &str,
+) {
+    if !raw.starts_with("{\"status\":\"OK\"") {
+        return;
+    }
+    let path = entry_path(method, params, api_key);
+    if let (Some(dir), Some(path)) = (cache_dir(), path) {
+        if DirBuilder::new().recursive(true).create(dir).is_ok() {
+            let _ = fs::write(path, raw);
+        }
+    }
+}
+
+/// Remove every cached response, returning the number of entries removed.
+pub fn clear() -> Result<usize, String> {
+    let dir = match cache_dir() {
+        Some(d) => d,
+        None => return Err("couldn't find a valid path for the cache".into()),
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("could not read cache dir: {}", e)),
+    };
+    let mut n = 0;
+    for entry in entries.flatten() {
+        if let Err(e) = fs::remove_file(entry.path()) {
+            return Err(format!("could not remove cache entry: {}", e));
+        }
+        n += 1;
+    }
+    Ok(n)
+}
+
+pub fn stats() -> Result<CacheStats, String> {
+    let dir = match cache_dir() {
+        Some(d) => d,
+        None => return Err("couldn't find a valid path for the cache".into()),
+    };
+    let mut res = CacheStats {
+        dir: dir.clone(),
+        entries: 0,
+        bytes: 0,
+        methods: BTreeMap::new(),
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(res),
+        Err(e) => return Err(format!("could not read cache dir: {}", e)),
+    };
+    for entry in entries.flatten() {
+        let size = entry.metadata().map_or(0, |m| m.len());
+        let name = entry.file_name().to_string_lossy().to_string();
+        let method = name.split('-').next().unwrap_or_default().to_string();
+        let m = res.methods.entry(method).or_insert((0, 0));
+        m.0 += 1;
+        m.1 += size;
+        res.entries += 1;
+        res.bytes += size;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    fn params(p: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+        p.iter().map(|(k, v)| (*k, v.to_string())).collect()
+    }
+
+    #[test]
+    fn entries_depend_on_api_key() {
+        let _dirs = testutil::isolated_dirs();
+        let p = params(&[("handles", "thud"), ("from", "1")]);
+        let path = entry_path("user.info", &p, "key").unwrap();
+        let reordered = params(&[("from", "1"), ("handles", "thud")]);
+        assert_eq!(entry_path("user.info", &reordered, "key").unwrap(), path);
+        for other in [
+            entry_path("user.info", &p, "other").unwrap(),
+            entry_path("user.info", &p, "").unwrap(),
+            entry_path("user.rating", &p, "key").unwrap(),
+            entry_path("user.info", &params(&[("handles", "x")]), "key")
+                .unwrap(),
+        ] {
+            assert_ne!(other, path);
+        }
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.starts_with("user.info-") && !name.contains("key"));
+    }
+
+    #[test]
+    fn cached_per_api_key() {
+        let _dirs = testutil::isolated_dirs();
+        let p = params(&[("gym", "false")]);
+        let online = CacheMode {
+            no_cache: false,
+            offline: false,
+        };
+        let raw = "{\"status\":\"OK\",\"result\":[]}";
+        put("contest.list", &p, "key", raw);
+        put("contest.list", &p, "other", "{\"status\":\"FAILED\"}");
+        assert_eq!(get(&online, "contest.list", &p, "key").unwrap(), raw);
+        assert_eq!(get(&online, "contest.list", &p, "other"), None);
+        // methods which aren't listed aren't fresh, except when offline
+        put("contest.standings", &p, "key", raw);
+        assert_eq!(get(&online, "contest.standings", &p, "key"), None);
+        let offline = CacheMode {
+            no_cache: false,
+            offline: true,
+        };
+        assert!(get(&offline, "contest.standings", &p, "key").is_some());
+        assert_eq!(get(&offline, "contest.standings", &p, "other"), None);
+        assert_eq!(stats().unwrap().entries, 2);
+        assert_eq!(clear(), Ok(2));
+    }
+}
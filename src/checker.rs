@@ -0,0 +1,345 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+pub const CHECKER_NAMES: &str = "lines, tokens, yesno, float[:<eps>], \
+                                 float-abs[:<eps>], float-rel[:<eps>], \
+                                 custom:<path>";
+
+/// Default epsilon used by the floating-point checkers.
+const DEFAULT_EPS: f64 = 1e-6;
+
+/// Exit code of a testlib checker which failed (eg. the answer was invalid),
+/// rather than rejecting the output.
+const CHECKER_FAILED: i32 = 3;
+
+/// How the output of a solution is compared against the expected output.
+#[derive(Clone, PartialEq, Default)]
+pub enum Checker {
+    /// Lines must match, ignoring trailing whitespace and blank lines.
+    Lines,
+    /// Whitespace separated tokens must match exactly.
+    #[default]
+    Tokens,
+    /// Tokens must match, ignoring case (eg. for YES/NO answers).
+    YesNo,
+    /// Tokens must match, with numbers compared with an absolute or relative
+    /// error of at most `abs`/`rel`.
+    Float { abs: Option<f64>, rel: Option<f64> },
+    /// A testlib style checker program, run as
+    /// `<path> <input> <output> <answer>`. The output is accepted if it exits
+    /// successfully, and rejected if it exits with another code than
+    /// [`CHECKER_FAILED`].
+    Custom(PathBuf),
+}
+
+impl FromStr for Checker {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, param) = match s.find(':') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+        let eps = || match param {
+            Some(p) => p.parse::<f64>().map_err(|_| {
+                format!("invalid epsilon `{}` for checker `{}`", p, name)
+            }),
+            None => Ok(DEFAULT_EPS),
+        };
+        match (name.to_lowercase().as_str(), param) {
+            ("lines", None) => Ok(Checker::Lines),
+            ("tokens", None) => Ok(Checker::Tokens),
+            ("yesno", None) => Ok(Checker::YesNo),
+            ("float", _) => {
+                let e = eps()?;
+                Ok(Checker::Float {
+                    abs: Some(e),
+                    rel: Some(e),
+                })
+            }
+            ("float-abs", _) => Ok(Checker::Float {
+                abs: Some(eps()?),
+                rel: None,
+            }),
+            ("float-rel", _) => Ok(Checker::Float {
+                abs: None,
+                rel: Some(eps()?),
+            }),
+            ("custom", Some(p)) if !p.is_empty() => {
+                Ok(Checker::Custom(PathBuf::from(p)))
+            }
+            _ => Err(format!(
+                "invalid checker `{}` (expected one of: {})",
+                s, CHECKER_NAMES
+            )),
+        }
+    }
+}
+
+/// Files passed to a custom checker.
+pub struct CheckerFiles<'a> {
+    pub input: &'a Path,
+    pub answer: &'a Path,
+}
+
+impl Checker {
+    /// Read the checker for a problem from a file (eg. `checker.txt` in the
+    /// problem's directory). Custom checker paths are relative to the
+    /// directory containing the file.
+    pub fn from_file(path: &Path) -> Result<Option<Checker>, String> {
+        let s = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(_) => return Ok(None),
+        };
+        let c = s
+            .parse::<Checker>()
+            .map_err(|e| format!("{} (in {})", e, path.display()))?;
+        Ok(Some(c.relative_to(path.parent().unwrap_or(Path::new("")))))
+    }
+
+    /// Make the path of a custom checker relative to `dir` (eg. the problem's
+    /// directory) if it's relative.
+    pub fn relative_to(self, dir: &Path) -> Checker {
+        match self {
+            Checker::Custom(p) if p.is_relative() => {
+                // Path::new(".") so that the checker isn't looked up in PATH.
+                let dir = Some(dir)
+                    .filter(|d| !d.as_os_str().is_empty())
+                    .unwrap_or_else(|| Path::new("."));
+                Checker::Custom(dir.join(p))
+            }
+            c => c,
+        }
+    }
+
+    /// Check the output of a solution, returning whether it was accepted and
+    /// an optional message explaining the result.
+    pub fn check(
+        &self,
+        expected: &str,
+        got: &str,
+        files: &CheckerFiles,
+    ) -> Result<(bool, Option<String>), String> {
+        match self {
+            Checker::Lines => Ok((lines(expected) == lines(got), None)),
+            Checker::Tokens => Ok(compare_tokens(expected, got, |a, b| a == b)),
+            Checker::YesNo => Ok(compare_tokens(expected, got, |a, b| {
+                a.eq_ignore_ascii_case(b)
+            })),
+            Checker::Float { abs, rel } => {
+                Ok(compare_tokens(expected, got, |a, b| {
+                    match (a.parse::<f64>(), b.parse::<f64>()) {
+                        (Ok(x), Ok(y)) => floats_match(x, y, *abs, *rel),
+                        _ => a == b,
+                    }
+                }))
+            }
+            Checker::Custom(path) => run_custom(path, got, files),
+        }
+    }
+}
+
+fn lines(s: &str) -> Vec<&str> {
+    let mut v: Vec<&str> = s.lines().map(|l| l.trim_end()).collect();
+    while v.last() == Some(&"") {
+        v.pop();
+    }
+    v
+}
+
+fn compare_tokens<F: Fn(&str, &str) -> bool>(
+    expected: &str,
+    got: &str,
+    eq: F,
+) -> (bool, Option<String>) {
+    let mut e = expected.split_whitespace();
+    let mut g = got.split_whitespace();
+    let mut n = 1;
+    loop {
+        match (e.next(), g.next()) {
+            (None, None) => return (true, None),
+            (Some(a), Some(b)) if eq(a, b) => n += 1,
+            (Some(a), Some(b)) => {
+                return (
+                    false,
+                    Some(format!(
+                        "token {} differs: expected `{}`, found `{}`",
+                        n, a, b
+                    )),
+                )
+            }
+            (Some(a), None) => {
+                return (
+                    false,
+                    Some(format!("expected `{}` at token {}, found EOF", a, n)),
+                )
+            }
+            (None, Some(b)) => {
+                return (
+                    false,
+                    Some(format!("extra output `{}` at token {}", b, n)),
+                )
+            }
+        }
+    }
+}
+
+fn floats_match(
+    expected: f64,
+    got: f64,
+    abs: Option<f64>,
+    rel: Option<f64>,
+) -> bool {
+    let diff = (expected - got).abs();
+    abs.is_some_and(|e| diff <= e + 1e-15)
+        || rel.is_some_and(|e| diff <= e * expected.abs() + 1e-15)
+}
+
+fn run_custom(
+    checker: &Path,
+    got: &str,
+    files: &CheckerFiles,
+) -> Result<(bool, Option<String>), String> {
+    let output = std::env::temp_dir()
+        .join(format!("caffeine-{}-output.txt", std::process::id()));
+    fs::write(&output, got)
+        .map_err(|e| format!("unable to write {}: {}", output.display(), e))?;
+    let res = Command::new(checker)
+        .arg(files.input)
+        .arg(&output)
+        .arg(files.answer)
+        .stdin(Stdio::null())
+        .output();
+    let _ = fs::remove_file(&output);
+    let out = res.map_err(|e| {
+        format!("unable to run checker {}: {}", checker.display(), e)
+    })?;
+    // testlib checkers print their comment to stderr.
+    let msg = [&out.stderr, &out.stdout]
+        .iter()
+        .map(|s| String::from_utf8_lossy(s).trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    match out.status.code() {
+        Some(0) => Ok((true, Some(msg).filter(|m| !m.is_empty()))),
+        Some(c) if c != CHECKER_FAILED => {
+            Ok((false, Some(msg).filter(|m| !m.is_empty())))
+        }
+        _ => Err(format!(
+            "checker {} failed ({}){}",
+            checker.display(),
+            out.status,
+            if msg.is_empty() {
+                String::new()
+            } else {
+                format!(":\n{}", msg)
+            }
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(checker: &str, expected: &str, got: &str) -> bool {
+        let files = CheckerFiles {
+            input: Path::new("in"),
+            answer: Path::new("ans"),
+        };
+        let c = checker.parse::<Checker>().unwrap();
+        c.check(expected, got, &files).unwrap().0
+    }
+
+    #[test]
+    fn builtin_checkers() {
+        for (checker, expected, got, ok) in [
+            ("tokens", "1 2\n3", "1\n2 3 ", true),
+            ("tokens", "1 2", "1 2 3", false),
+            ("lines", "1 2  \n3\n\n", "1 2\n3", true),
+            ("lines", "1 2\n3", "1\n2 3", false),
+            ("yesno", "YES\nno", "yes NO", true),
+            ("yesno", "YES", "NO", false),
+            ("float", "0.5 x", "0.5000001 x", true),
+            ("float", "0.5", "0.51", false),
+            ("float-abs:0.1", "100", "100.05", true),
+            ("float-rel:0.1", "100", "105", true),
+            ("float-abs:0.1", "100", "105", false),
+        ] {
+            assert_eq!(check(checker, expected, got), ok, "{}", checker);
+        }
+    }
+
+    #[test]
+    fn parses_checkers() {
+        assert!("float:x".parse::<Checker>().is_err());
+        assert!("custom:".parse::<Checker>().is_err());
+        assert!("tokens:1".parse::<Checker>().is_err());
+        assert!(
+            Checker::Float {
+                abs: Some(0.5),
+                rel: None
+            } == "Float-Abs:0.5".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn custom_paths_are_relative_to_problem_dir() {
+        let dir = Path::new("contest/a");
+        for (checker, path) in [
+            ("custom:chk", "contest/a/chk"),
+            ("custom:../chk", "contest/a/../chk"),
+            ("custom:/usr/bin/chk", "/usr/bin/chk"),
+        ] {
+            let c = checker.parse::<Checker>().unwrap().relative_to(dir);
+            assert!(c == Checker::Custom(PathBuf::from(path)), "{}", checker);
+        }
+        let c = "custom:chk".parse::<Checker>().unwrap();
+        assert!(
+            c.relative_to(Path::new("")) == Checker::Custom("./chk".into())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_custom_checkers() {
+        let _dirs = crate::testutil::isolated_dirs();
+        let chk = crate::testutil::write_script(
+            "chk",
+            r#"[ "$(cat "$2")" = "$(cat "$3")" ] && exit 0
+[ "$(cat "$3")" = bad ] && { echo "invalid answer" >&2; exit 3; }
+echo "wrong answer" >&2; echo "see line 1"; exit 1
+"#,
+        );
+        let dir = chk.parent().unwrap();
+        std::fs::write(dir.join("ans"), "42").unwrap();
+        std::fs::write(dir.join("bad"), "bad").unwrap();
+        let path = dir.join("checker.txt");
+        std::fs::write(&path, "custom:chk").unwrap();
+        let c = Checker::from_file(&path).unwrap().unwrap();
+        let input = dir.join("in");
+        let check = |got: &str, answer: &str| {
+            let answer = dir.join(answer);
+            let files = CheckerFiles {
+                input: &input,
+                answer: &answer,
+            };
+            c.check("", got, &files)
+        };
+        assert_eq!(check("42", "ans"), Ok((true, None)));
+        assert_eq!(
+            check("41", "ans"),
+            Ok((false, Some("wrong answer\nsee line 1".to_string())))
+        );
+        let e = check("41", "bad").unwrap_err();
+        assert!(
+            e.contains("failed") && e.ends_with(":\ninvalid answer"),
+            "{}",
+            e
+        );
+    }
+}
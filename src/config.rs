@@ -1,19 +1,127 @@
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::{DirBuilder, File, OpenOptions};
 use std::io::{ErrorKind, Read, Result as IoResult, Write};
+use std::sync::Once;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct Config {
     pub default_user: Option<String>,
     pub default_program_type_id: Option<i64>,
+    /// Number of seconds to cache responses for, keyed by API method name
+    /// (eg. `contest.list: 3600`).
+    pub cache_ttl: Option<BTreeMap<String, u64>>,
+    /// Number of times to retry API requests which failed temporarily.
+    pub retries: Option<u32>,
+    /// Minimum number of seconds between API requests.
+    pub min_interval: Option<f64>,
+    /// Number of seconds `caffeine submit --wait` waits for a submission to
+    /// be judged before giving up.
+    pub judging_timeout: Option<f64>,
+    /// Layout of directories created by `caffeine contest setup`.
+    pub workspace: Option<WorkspaceConfig>,
+    /// programTypeId used for solution files, keyed by file extension (eg.
+    /// `py: 31`).
+    pub extensions: Option<BTreeMap<String, i64>>,
+    /// Commands used by `caffeine test`, keyed by programTypeId.
+    pub languages: Option<BTreeMap<i64, LanguageConfig>>,
+    /// Time limit (in seconds) for each testcase run by `caffeine test`.
+    pub time_limit: Option<f64>,
+    /// Default checker used by `caffeine test` (eg. `tokens` or `float:1e-6`).
+    pub checker: Option<String>,
+    /// Run `caffeine submit` solutions against their samples before
+    /// submitting.
+    pub test_first: Option<bool>,
+    /// Backend used by `caffeine submit` (`chrome` or `http`).
+    pub submit_backend: Option<String>,
+    /// Base URL of Codeforces used when submitting (eg. a mirror, or a local
+    /// server for testing).
+    pub base_url: Option<String>,
+    /// Transforms applied to solutions before `caffeine submit` sends them.
+    pub preprocess: Option<PreprocessConfig>,
+    /// Where `caffeine login` keeps the API secret and password rather than
+    /// in auth.yml (`secret-service` or `file`).
+    pub secret_store: Option<String>,
+    /// Program used to access the Secret Service (default `secret-tool`).
+    pub secret_tool: Option<String>,
+    /// Refuse to read auth.yml when other users can access it (rather than
+    /// only warning).
+    pub strict_permissions: Option<bool>,
+    /// Command printing the credentials to use rather than those in auth.yml
+    /// (eg. `pass show codeforces`), see [`crate::credential`].
+    pub credential_command: Option<String>,
+}
+
+/// Settings of [`crate::preprocess::Preprocessor`] (every transform is
+/// disabled by default).
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct PreprocessConfig {
+    /// Inline local `#include "..."` headers (C/C++) and `mod name;` files
+    /// (Rust) into the solution.
+    pub inline: Option<bool>,
+    /// Directories searched for headers/modules which aren't found next to
+    /// the file including them.
+    pub include_dirs: Option<Vec<String>>,
+    /// Remove blocks of lines between lines containing `debug_begin` and
+    /// `debug_end`.
+    pub strip_debug: Option<bool>,
+    pub debug_begin: Option<String>,
+    pub debug_end: Option<String>,
+    /// Append a comment with random contents, so that resubmitting the same
+    /// solution isn't rejected.
+    pub random_comment: Option<bool>,
+}
+
+/// Commands used to compile and run a solution. Commands are run with `sh -c`
+/// and may contain the placeholders `<file>` (the solution), `<exe>` (a
+/// temporary path for the compiled program) and `<dir>` (directory of the
+/// solution).
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LanguageConfig {
+    pub compile: Option<String>,
+    pub run: String,
+}
+
+/// Filename patterns used by `caffeine contest setup`. See
+/// [`crate::workspace::WorkspaceSettings`] for the supported placeholders.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct WorkspaceConfig {
+    pub problem_dir: Option<String>,
+    pub solution_filename: Option<String>,
+    pub input_filename: Option<String>,
+    pub output_filename: Option<String>,
+    /// File in the problem directory naming the checker for the problem.
+    pub checker_filename: Option<String>,
+    /// Path to a file whose contents are used for new solution files.
+    pub template: Option<String>,
 }
 
 pub fn get_config() -> Result<Config, &'static str> {
-    get_config_from_file()
+    match read_config_file()? {
+        Some(c) => Ok(c),
+        None => Err("(config.yml not found) use `caffeine config` to setup \
+            defaults."),
+    }
+}
+
+/// Like [`get_config`], but falls back to the defaults if config.yml can't be
+/// used. Unless config.yml doesn't exist, this is warned about on stderr (once
+/// per invocation) rather than silently ignoring the user's settings.
+pub fn get_config_or_default() -> Config {
+    static WARNED: Once = Once::new();
+    read_config_file()
+        .unwrap_or_else(|e| {
+            WARNED.call_once(|| {
+                eprintln!("warning: {}, using the default settings", e)
+            });
+            None
+        })
+        .unwrap_or_default()
 }
 
-fn get_config_from_file() -> Result<Config, &'static str> {
+/// Read and parse config.yml, giving `None` if it doesn't exist.
+fn read_config_file() -> Result<Option<Config>, &'static str> {
     match ProjectDirs::from(crate::NAME_QUL, crate::NAME_ORG, crate::NAME_BIN) {
         Some(proj_dirs) => {
             let f = open_config_file(proj_dirs, false);
@@ -31,16 +139,13 @@ fn get_config_from_file() -> Result<Config, &'static str> {
                             ),
                         },
                         Ok(_) => match serde_yaml::from_str::<Config>(&s) {
-                            Ok(a) => Ok(a),
+                            Ok(a) => Ok(Some(a)),
                             Err(_) => Err("failed to parse config.yml file"),
                         },
                     }
                 }
                 Err(e) => match e.kind() {
-                    ErrorKind::NotFound => {
-                        Err("(config.yml not found) use `caffeine config` to \
-                        setup defaults.")
-                    }
+                    ErrorKind::NotFound => Ok(None),
                     ErrorKind::PermissionDenied => {
                         Err("could not open config.yml, permission denied")
                     }
@@ -58,27 +163,16 @@ pub fn set_config(
 ) -> Result<(), &'static str> {
     match ProjectDirs::from(crate::NAME_QUL, crate::NAME_ORG, crate::NAME_BIN) {
         Some(proj_dirs) => {
-            let mut x = Config {
-                default_user: None,
-                default_program_type_id: None,
-            };
-            if default_user.is_some() || default_program_type_id.is_some() {
-                x = Config {
-                    default_user,
-                    default_program_type_id,
-                };
+            // Defaults are only written if there's no config file yet, since
+            // overwriting one which can't be read would lose its settings
+            let mut x = read_config_file()?.unwrap_or_default();
+            // If no details provided then use those already stored
+            if default_user.is_some() {
+                x.default_user = default_user;
             }
-            if let Ok(a) = get_config_from_file() {
-                // If no details provided then use those already stored
-                if x.default_user.is_none() {
-                    x.default_user = a.default_user;
-                }
-                if x.default_program_type_id.is_none() {
-                    x.default_program_type_id = a.default_program_type_id;
-                }
+            if default_program_type_id.is_some() {
+                x.default_program_type_id = default_program_type_id;
             }
-            // If config file not successfully opened/parsed, then ignore
-            // and overwrite all
 
             // unwrap is probably ok here since serializing errors are very rare
             let s = serde_yaml::to_string(&x).unwrap();
@@ -132,3 +226,54 @@ fn open_config_file(proj_dirs: ProjectDirs, write: bool) -> IoResult<File> {
         Err(e) => Err(e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    fn read() -> String {
+        let dir = testutil::proj_dirs().config_dir().to_path_buf();
+        std::fs::read_to_string(dir.join(crate::CONF_FILE_NAME)).unwrap()
+    }
+
+    #[test]
+    fn falls_back_to_defaults() {
+        let _dirs = testutil::isolated_dirs();
+        assert!(get_config().is_err());
+        assert!(get_config_or_default().retries.is_none());
+        testutil::write_config("retries: 5\n");
+        assert_eq!(get_config().unwrap().retries, Some(5));
+        assert_eq!(get_config_or_default().retries, Some(5));
+        testutil::write_config("retries: [5\n");
+        assert_eq!(get_config().err(), Some("failed to parse config.yml file"));
+        assert!(get_config_or_default().retries.is_none());
+    }
+
+    #[test]
+    fn sets_config() {
+        let _dirs = testutil::isolated_dirs();
+        // defaults are written when there's no config.yml
+        set_config(Some("thud".to_string()), None).unwrap();
+        let c = get_config().unwrap();
+        assert_eq!(c.default_user.as_deref(), Some("thud"));
+        assert_eq!(c.default_program_type_id, None);
+
+        // other settings are kept
+        testutil::write_config("default_user: thud\nretries: 5\n");
+        set_config(None, Some(54)).unwrap();
+        let c = get_config().unwrap();
+        assert_eq!(c.default_user.as_deref(), Some("thud"));
+        assert_eq!(c.default_program_type_id, Some(54));
+        assert_eq!(c.retries, Some(5));
+
+        // an unparsable config.yml isn't overwritten
+        let broken = "default_user: thud\nretries: [5\n";
+        testutil::write_config(broken);
+        assert_eq!(
+            set_config(Some("tourist".to_string()), None),
+            Err("failed to parse config.yml file")
+        );
+        assert_eq!(read(), broken);
+    }
+}
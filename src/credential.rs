@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Environment variable telling `credential_command` which credentials are
+/// needed (`login` or `api`), for commands which print them separately.
+pub const KIND_ENV: &str = "CAFFEINE_CREDENTIAL";
+pub const LOGIN: &str = "login";
+pub const API: &str = "api";
+
+/// Names of the fields holding each credential, either as `name=value` (like
+/// git credential helpers) or `name: value` (like entries of `pass`).
+const HANDLE_FIELDS: &[&str] = &["handle", "username", "login", "email"];
+const PASSWORD_FIELDS: &[&str] = &["password"];
+const API_KEY_FIELDS: &[&str] = &["api_key"];
+const API_SECRET_FIELDS: &[&str] = &["api_secret"];
+
+/// Output of each `credential_command` run so far, by command and kind, so
+/// that it's only run once per process (it may prompt for a passphrase).
+static CACHE: Mutex<BTreeMap<(String, String), Credentials>> =
+    Mutex::new(BTreeMap::new());
+
+/// Credentials printed by `credential_command` in config.yml.
+#[derive(Clone)]
+pub struct Credentials {
+    fields: BTreeMap<String, String>,
+    /// First line of the output if it isn't a field, which is taken as the
+    /// password (like the first line of a `pass` entry, or the output of
+    /// `op read`).
+    first_line: Option<String>,
+}
+
+fn is_field(name: &str) -> bool {
+    [
+        HANDLE_FIELDS,
+        PASSWORD_FIELDS,
+        API_KEY_FIELDS,
+        API_SECRET_FIELDS,
+    ]
+    .iter()
+    .any(|names| names.contains(&name))
+}
+
+/// Name and value of a line of the form `name=value` or `name: value`, if
+/// `name` is a known field. If `strict`, the line has to be exactly of that
+/// form (eg. `login:xyz` isn't a field), since it could be a password.
+/// Passwords are kept as they are, apart from the space after `:`.
+fn parse_field(line: &str, strict: bool) -> Option<(String, String)> {
+    let i = line.find(['=', ':'])?;
+    let (name, rest) = (&line[..i], &line[i + 1..]);
+    let value = match line.as_bytes()[i] {
+        b':' if strict => rest.strip_prefix(' ')?,
+        b':' => rest.strip_prefix(' ').unwrap_or(rest),
+        _ => rest,
+    };
+    if strict && name.trim() != name {
+        return None;
+    }
+    let name = name.trim().to_lowercase();
+    if !is_field(&name) {
+        return None;
+    }
+    let value = if PASSWORD_FIELDS.contains(&name.as_str()) {
+        value
+    } else {
+        value.trim()
+    };
+    Some((name, value.to_string()))
+}
+
+impl Credentials {
+    pub fn parse(output: &str) -> Credentials {
+        let mut fields = BTreeMap::new();
+        let mut first_line = None;
+        for (i, line) in output.lines().enumerate() {
+            match parse_field(line, i == 0) {
+                Some((name, value)) => {
+                    fields.entry(name).or_insert(value);
+                }
+                None if i == 0 && !line.is_empty() => {
+                    first_line = Some(line.to_string());
+                }
+                None => {}
+            }
+        }
+        Credentials { fields, first_line }
+    }
+
+    fn field(&self, names: &[&str]) -> Option<String> {
+        names
+            .iter()
+            .find_map(|n| self.fields.get(*n))
+            .filter(|v| !v.is_empty())
+            .cloned()
+    }
+
+    /// Handle and password (None unless both are known). `handle` (eg. from
+    /// `--handle`) is used rather than the one printed.
+    pub fn login(&self, handle: Option<String>) -> Option<(String, String)> {
+        let h = handle.or_else(|| self.field(HANDLE_FIELDS))?;
+        let p = self
+            .field(PASSWORD_FIELDS)
+            .or_else(|| self.first_line.clone())?;
+        Some((h, p))
+    }
+
+    /// API key and secret (None unless both are known). `key` is used rather
+    /// than the one printed.
+    pub fn api(&self, key: Option<String>) -> Option<(String, String)> {
+        let k = key.or_else(|| self.field(API_KEY_FIELDS))?;
+        let s = self.field(API_SECRET_FIELDS)?;
+        Some((k, s))
+    }
+}
+
+/// Run `command` (with `sh -c`) to get the `kind` of credentials, unless it
+/// has already been run. The command can still prompt (eg. for the
+/// passphrase of a password manager) since stdin and stderr are inherited.
+pub fn run(command: &str, kind: &str) -> Result<Credentials, String> {
+    let key = (command.to_string(), kind.to_string());
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(c) = cache.get(&key) {
+        return Ok(c.clone());
+    }
+    let out = Command::new("sh")
+        .args(["-c", command])
+        .env(KIND_ENV, kind)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("unable to run credential_command: {}", e))?;
+    if !out.status.success() {
+        return Err(format!(
+            "credential_command `{}` failed ({})",
+            command, out.status
+        ));
+    }
+    let c = Credentials::parse(&String::from_utf8_lossy(&out.stdout));
+    cache.insert(key, c.clone());
+    Ok(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login(output: &str) -> Option<(String, String)> {
+        Credentials::parse(output).login(None)
+    }
+
+    #[test]
+    fn parses_login() {
+        let hp = |h: &str, p: &str| Some((h.to_string(), p.to_string()));
+        for (output, expected) in [
+            // git credential helpers
+            ("username=thud\npassword=hunter2\n", hp("thud", "hunter2")),
+            ("host=x\nusername=thud\npassword=x=y\n", hp("thud", "x=y")),
+            // pass
+            ("hunter2\nlogin: thud\nurl: x\n", hp("thud", "hunter2")),
+            ("hunter2\nLogin : thud\n", hp("thud", "hunter2")),
+            ("login:xyz\nlogin: thud\n", hp("thud", "login:xyz")),
+            ("login : xyz\nlogin: thud\n", hp("thud", "login : xyz")),
+            ("password:x\nemail: a@b.c\n", hp("a@b.c", "password:x")),
+            ("login: thud\npassword: hunter2\n", hp("thud", "hunter2")),
+            // passwords aren't trimmed
+            ("  hunter2 \nlogin: thud\n", hp("thud", "  hunter2 ")),
+            (
+                "login: thud\npassword:  hunter2 \n",
+                hp("thud", " hunter2 "),
+            ),
+            ("handle=thud\npassword= hunter2\n", hp("thud", " hunter2")),
+            // the first field wins
+            (
+                "login: a\nemail: b\npassword: 1\npassword: 2\n",
+                hp("a", "1"),
+            ),
+            // incomplete
+            ("hunter2\n", None),
+            ("login: thud\n", None),
+            ("\nlogin: thud\n", None),
+            ("login: thud\npassword:\n", None),
+            ("", None),
+        ] {
+            assert_eq!(login(output), expected, "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn handle_given() {
+        let c = Credentials::parse("hunter2\n");
+        assert_eq!(
+            c.login(Some("thud".to_string())),
+            Some(("thud".to_string(), "hunter2".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_api() {
+        let c = Credentials::parse("api_key=abc\napi_secret: def \n");
+        assert_eq!(c.api(None), Some(("abc".to_string(), "def".to_string())));
+        assert_eq!(
+            c.api(Some("xyz".to_string())),
+            Some(("xyz".to_string(), "def".to_string()))
+        );
+        assert_eq!(Credentials::parse("api_key=abc\n").api(None), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_once() {
+        let _dirs = crate::testutil::isolated_dirs();
+        let count = crate::testutil::write_script("count", "");
+        let command = format!(
+            "echo run >> {0}.log; printf 'hunter2\\nlogin: %s\\n' \"${1}\"",
+            count.display(),
+            KIND_ENV
+        );
+        for _ in 0..2 {
+            let c = run(&command, LOGIN).unwrap();
+            assert_eq!(
+                c.login(None),
+                Some(("login".to_string(), "hunter2".to_string()))
+            );
+        }
+        assert_eq!(
+            run(&command, API).unwrap().login(None),
+            Some(("api".to_string(), "hunter2".to_string()))
+        );
+        let log = std::fs::read_to_string(format!("{}.log", count.display()));
+        assert_eq!(log.unwrap(), "run\nrun\n");
+        assert!(run("exit 3", LOGIN).is_err());
+    }
+}
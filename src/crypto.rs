@@ -0,0 +1,202 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key as CipherKey, Nonce};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+const KDF: &str = "argon2id";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+/// Largest key derivation parameters accepted from encrypted data, so that a
+/// tampered file can't make deriving the key use gigabytes of memory (or take
+/// hours). The defaults are 19 MiB, 2 iterations and 1 lane.
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 32;
+const MAX_P_COST: u32 = 16;
+
+/// Data encrypted with a key derived from a passphrase, along with everything
+/// (except the passphrase) needed to decrypt it. Binary fields are hex.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Sealed {
+    kdf: String,
+    /// Argon2 memory cost (in KiB), iterations and parallelism.
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Key derived from a passphrase (and the salt it was derived with).
+#[derive(Clone)]
+pub struct Key {
+    params: (u32, u32, u32),
+    salt: Vec<u8>,
+    key: [u8; KEY_LEN],
+}
+
+fn derive(
+    passphrase: &str,
+    salt: &[u8],
+    (m_cost, t_cost, p_cost): (u32, u32, u32),
+) -> Result<[u8; KEY_LEN], String> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(KEY_LEN))
+        .map_err(|e| format!("invalid key derivation parameters: {}", e))?;
+    let mut key = [0; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("unable to derive key: {}", e))?;
+    Ok(key)
+}
+
+fn unhex(s: &str, what: &str) -> Result<Vec<u8>, String> {
+    hex::decode(s).map_err(|_| format!("invalid {} in encrypted data", what))
+}
+
+impl Key {
+    /// Derive a key for encrypting with a fresh salt.
+    pub fn new(passphrase: &str) -> Result<Key, String> {
+        let params = (
+            Params::DEFAULT_M_COST,
+            Params::DEFAULT_T_COST,
+            Params::DEFAULT_P_COST,
+        );
+        let salt = rand::thread_rng().gen::<[u8; SALT_LEN]>().to_vec();
+        let key = derive(passphrase, &salt, params)?;
+        Ok(Key { params, salt, key })
+    }
+
+    /// Derive the key which `sealed` was encrypted with.
+    pub fn for_sealed(
+        passphrase: &str,
+        sealed: &Sealed,
+    ) -> Result<Key, String> {
+        if sealed.kdf != KDF {
+            return Err(format!("unsupported key derivation `{}`", sealed.kdf));
+        }
+        if sealed.m_cost > MAX_M_COST
+            || sealed.t_cost > MAX_T_COST
+            || sealed.p_cost > MAX_P_COST
+        {
+            return Err(format!(
+                "key derivation parameters of encrypted data are too large \
+                (at most {} KiB, {} iterations and {} lanes)",
+                MAX_M_COST, MAX_T_COST, MAX_P_COST
+            ));
+        }
+        let params = (sealed.m_cost, sealed.t_cost, sealed.p_cost);
+        let salt = unhex(&sealed.salt, "salt")?;
+        let key = derive(passphrase, &salt, params)?;
+        Ok(Key { params, salt, key })
+    }
+
+    /// Key previously exported with [`Key::export`], for data sealed with the
+    /// same salt as `sealed`.
+    pub fn import(exported: &str, sealed: &Sealed) -> Result<Key, String> {
+        let key = hex::decode(exported.trim())
+            .ok()
+            .and_then(|k| <[u8; KEY_LEN]>::try_from(k.as_slice()).ok())
+            .ok_or("invalid key")?;
+        Ok(Key {
+            params: (sealed.m_cost, sealed.t_cost, sealed.p_cost),
+            salt: unhex(&sealed.salt, "salt")?,
+            key,
+        })
+    }
+
+    /// The key itself (hex), which decrypts without the passphrase.
+    pub fn export(&self) -> String {
+        hex::encode(self.key)
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(CipherKey::from_slice(&self.key))
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Sealed, String> {
+        let nonce = rand::thread_rng().gen::<[u8; NONCE_LEN]>();
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| "encryption failed")?;
+        let (m_cost, t_cost, p_cost) = self.params;
+        Ok(Sealed {
+            kdf: KDF.to_string(),
+            m_cost,
+            t_cost,
+            p_cost,
+            salt: hex::encode(&self.salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    /// Decrypt `sealed`, failing if the key is wrong (or the data was
+    /// tampered with).
+    pub fn open(&self, sealed: &Sealed) -> Result<Vec<u8>, String> {
+        let nonce = unhex(&sealed.nonce, "nonce")?;
+        if nonce.len() != NONCE_LEN {
+            return Err("invalid nonce in encrypted data".into());
+        }
+        let ciphertext = unhex(&sealed.ciphertext, "ciphertext")?;
+        self.cipher()
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "wrong passphrase (or corrupted data)".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_open_round_trip() {
+        let key = Key::new("correct horse").unwrap();
+        let sealed = key.seal(b"api:\n  key: abc\n").unwrap();
+        assert_eq!(key.open(&sealed).unwrap(), b"api:\n  key: abc\n");
+        // a key derived again from the passphrase opens it too
+        let again = Key::for_sealed("correct horse", &sealed).unwrap();
+        assert_eq!(again.open(&sealed).unwrap(), b"api:\n  key: abc\n");
+        // as does the exported key
+        let imported = Key::import(&key.export(), &sealed).unwrap();
+        assert_eq!(imported.open(&sealed).unwrap(), b"api:\n  key: abc\n");
+        // nonces are never reused
+        assert_ne!(
+            key.seal(b"x").unwrap().nonce,
+            key.seal(b"x").unwrap().nonce
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let sealed =
+            Key::new("correct horse").unwrap().seal(b"secret").unwrap();
+        let key = Key::for_sealed("battery staple", &sealed).unwrap();
+        let e = key.open(&sealed).unwrap_err();
+        assert!(e.contains("wrong passphrase"), "{}", e);
+    }
+
+    #[test]
+    fn tampered_data() {
+        let key = Key::new("correct horse").unwrap();
+        let mut sealed = key.seal(b"secret").unwrap();
+        let mut ciphertext = hex::decode(&sealed.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        sealed.ciphertext = hex::encode(ciphertext);
+        assert!(key.open(&sealed).is_err());
+
+        let mut sealed = key.seal(b"secret").unwrap();
+        sealed.m_cost = 16 * 1024 * 1024;
+        let e = Key::for_sealed("correct horse", &sealed).err().unwrap();
+        assert!(e.contains("too large"), "{}", e);
+        sealed.m_cost = Params::DEFAULT_M_COST;
+        sealed.t_cost = u32::MAX;
+        assert!(Key::for_sealed("correct horse", &sealed).is_err());
+        sealed.t_cost = Params::DEFAULT_T_COST;
+        sealed.kdf = "scrypt".to_string();
+        assert!(Key::for_sealed("correct horse", &sealed).is_err());
+    }
+}
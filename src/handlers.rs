@@ -2,30 +2,46 @@
 
 use clap::{value_t, values_t, ArgMatches};
 use codeforces_api::requests::*;
-use codeforces_api::responses::CFResult;
+use codeforces_api::responses::{CFContest, CFProblem, CFResult};
 use codeforces_api::Error as ApiError;
 use std::fs::File;
 use std::io::{stdin, stdout, Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-use crate::{auth, config, submit};
+use crate::preprocess::Preprocessor;
+use crate::submit_error::SubmitError;
+use crate::target::{ProblemId, Target};
+use crate::testcases::{self, Testcase};
+use crate::{
+    api, auth, cache, checker, config, credential, http_submit, languages,
+    output, permissions, runner, session, submit, target, verdict, workspace,
+};
 
 fn get_from_api<T: CFAPIRequestable>(args: &ArgMatches, x: &T) -> CFResult {
-    let (k, s) = get_api_key_secret(args);
-    match x.get(&k, &s) {
+    match api::request(args, x) {
         Ok(s) => s,
         Err(e) => exit_with_error(e),
     }
 }
 
 fn get_from_api_raw<T: CFAPIRequestable>(args: &ArgMatches, x: &T) -> String {
-    let (k, s) = get_api_key_secret(args);
-    match x.get_raw(&k, &s) {
+    match api::request_raw(args, x) {
         Ok(s) => s,
         Err(e) => exit_with_error(e),
     }
 }
 
+/// Fetch the result of a request and print it in the format requested on the
+/// command line (or as the undecoded response if `--raw` was given).
+fn print_from_api<T: CFAPIRequestable>(args: &ArgMatches, x: &T) {
+    if args.is_present("raw") {
+        println!("{}", get_from_api_raw(args, x));
+    } else {
+        output::print_result(args, &get_from_api(args, x));
+    }
+}
+
 pub fn get_optional_arg_of_type<T: std::str::FromStr>(
     args: &ArgMatches,
     name: &str,
@@ -38,6 +54,20 @@ pub fn get_optional_arg_of_type<T: std::str::FromStr>(
     }
 }
 
+/// Contest id given by the `CONTESTID` argument (which may also be a problem
+/// code or url, see [`ProblemId`]).
+fn get_contest_id(args: &ArgMatches) -> i64 {
+    get_contest_target(args).contest_id()
+}
+
+/// The contest given as `CONTESTID` (a gym or group contest if its url was
+/// given).
+fn get_contest_target(args: &ArgMatches) -> Target {
+    get_optional_arg_of_type::<ProblemId>(args, "CONTESTID")
+        .unwrap()
+        .target
+}
+
 pub fn get_optional_args_of_type<T: std::str::FromStr>(
     args: &ArgMatches,
     name: &str,
@@ -60,13 +90,53 @@ pub fn exit_with_error<E: std::fmt::Display>(e: E) -> ! {
     std::process::exit(2);
 }
 
+/// Like [`exit_with_error`], exiting with the status for the kind of error.
+fn exit_with_submit_error(e: &SubmitError) -> ! {
+    eprintln!("Error: {}", e);
+    std::process::exit(e.exit_code());
+}
+
+/// Value of the flag `name`, else of the environment variable `var` (unless
+/// it's empty).
+fn get_arg_or_env(args: &ArgMatches, name: &str, var: &str) -> Option<String> {
+    get_optional_arg_of_type(args, name)
+        .or_else(|| std::env::var(var).ok().filter(|v| !v.is_empty()))
+}
+
+/// Credentials printed by `credential_command` in config.yml (None if it isn't
+/// set).
+fn get_credentials(
+    kind: &str,
+) -> Result<Option<credential::Credentials>, String> {
+    let conf = config::get_config_or_default();
+    conf.credential_command
+        .map(|c| credential::run(&c, kind))
+        .transpose()
+}
+
+/// The API key and secret, which are only looked up once per process since
+/// they're needed for every API request (eg. whilst polling for a verdict)
+/// and looking them up may prompt for a passphrase.
+static API_KEY_SECRET: OnceLock<(String, String)> = OnceLock::new();
+
 pub fn get_api_key_secret(args: &ArgMatches) -> (String, String) {
-    let key = get_optional_arg_of_type(args, "key");
-    let sec = get_optional_arg_of_type(args, "secret");
+    API_KEY_SECRET
+        .get_or_init(|| lookup_api_key_secret(args))
+        .clone()
+}
+
+fn lookup_api_key_secret(args: &ArgMatches) -> (String, String) {
+    let key = get_arg_or_env(args, "key", auth::API_KEY_ENV);
+    let sec = get_arg_or_env(args, "secret", auth::API_SECRET_ENV);
     match (key, sec) {
         (Some(k), Some(s)) => (k, s),
-        _ => {
-            let res = auth::get_api_key_secret_from_file();
+        (key, _) => {
+            // Fall back to auth.yml if credential_command didn't print them
+            let res = get_credentials(credential::API)
+                .map(|c| c.and_then(|c| c.api(key)))
+                .and_then(|ks| {
+                    ks.map_or_else(auth::get_api_key_secret_from_file, Ok)
+                });
             match res {
                 Ok(ks) => ks,
                 Err(e) => exit_with_error(e),
@@ -75,19 +145,88 @@ pub fn get_api_key_secret(args: &ArgMatches) -> (String, String) {
     }
 }
 
-fn get_login_details(args: &ArgMatches) -> (String, String) {
-    let handle = get_optional_arg_of_type(args, "handle");
-    let password = get_optional_arg_of_type(args, "password");
+fn try_get_login_details(
+    args: &ArgMatches,
+) -> Result<(String, String), String> {
+    let handle = get_arg_or_env(args, "handle", auth::HANDLE_ENV);
+    let password = get_arg_or_env(args, "password", auth::PASSWORD_ENV);
     match (handle, password) {
-        (Some(h), Some(p)) => (h, p),
-        _ => {
-            let res = auth::get_login_details_from_file();
-            match res {
-                Ok(hp) => hp,
-                Err(e) => exit_with_error(e),
-            }
-        }
+        (Some(h), Some(p)) => Ok((h, p)),
+        (handle, _) => get_credentials(credential::LOGIN)
+            .map(|c| c.and_then(|c| c.login(handle)))
+            .and_then(|hp| {
+                hp.map_or_else(auth::get_login_details_from_file, Ok)
+            }),
+    }
+}
+
+fn get_login_details(args: &ArgMatches) -> (String, String) {
+    match try_get_login_details(args) {
+        Ok(hp) => hp,
+        Err(e) => exit_with_error(e),
+    }
+}
+
+/// Base URL of the site to submit to (`--base-url`, else `base_url` in
+/// config.yml, else the `--mirror`), always ending in a slash.
+fn get_base_url(args: &ArgMatches, conf: &config::Config) -> String {
+    let m = get_optional_arg_of_type(args, "mirror");
+    let mut base_url = get_optional_arg_of_type(args, "baseurl")
+        .or_else(|| conf.base_url.clone())
+        .unwrap_or_else(|| submit::mirror_url(m).to_string());
+    if !base_url.ends_with('/') {
+        base_url.push('/');
+    }
+    base_url
+}
+
+/// Prompt (twice) for the passphrase used by `caffeine login --encrypt`.
+fn get_new_passphrase() -> String {
+    let prompt = |p: &str| {
+        rpassword::prompt_password(p).unwrap_or_else(|e| {
+            exit_with_error(format!("unable to read passphrase: {}", e))
+        })
+    };
+    let passphrase = prompt("passphrase to encrypt auth.yml with: ");
+    if passphrase.is_empty() {
+        exit_with_error("passphrase can't be empty");
+    }
+    if prompt("passphrase (again): ") != passphrase {
+        exit_with_error("passphrases don't match");
+    }
+    passphrase
+}
+
+/// Prompt for a line of input, without echoing it if it's `secret` (and stdin
+/// is a terminal).
+fn prompt_line(msg: &str, secret: bool) -> String {
+    if secret && atty::is(atty::Stream::Stdin) {
+        return match rpassword::prompt_password(msg) {
+            Ok(s) => s.trim().to_string(),
+            Err(e) => exit_with_error(e),
+        };
+    }
+    print!("{}", msg);
+    stdout().flush().expect("unable to flush stdout?");
+    let mut s = String::new();
+    if let Err(e) = stdin().read_line(&mut s) {
+        exit_with_error(e);
+    }
+    s.trim().to_string()
+}
+
+/// Read the password for `caffeine login --password-stdin` (all of stdin,
+/// without trailing newlines).
+fn read_password_stdin() -> String {
+    let mut p = String::new();
+    if let Err(e) = stdin().read_to_string(&mut p) {
+        exit_with_error(e);
     }
+    let p = p.trim_end_matches(&['\r', '\n'][..]).to_string();
+    if p.is_empty() {
+        exit_with_error("no password given on stdin");
+    }
+    p
 }
 
 pub fn login_command(args: &ArgMatches) {
@@ -95,39 +234,32 @@ pub fn login_command(args: &ArgMatches) {
     let key = get_optional_arg_of_type(args, "key");
     let sec = get_optional_arg_of_type(args, "secret");
     let handle = get_optional_arg_of_type(args, "handle");
-    let password = get_optional_arg_of_type(args, "password");
+    let password = if args.is_present("password-stdin") {
+        Some(read_password_stdin())
+    } else {
+        get_optional_arg_of_type(args, "password")
+    };
+    let passphrase = if args.is_present("encrypt") {
+        Some(get_new_passphrase())
+    } else {
+        None
+    };
 
     if (key.is_some() && sec.is_some())
         || (handle.is_some() && password.is_some())
     {
-        match auth::set_auth_creds(key, sec, handle, password) {
+        match auth::set_auth_creds(key, sec, handle, password, passphrase) {
             Ok(()) => {
                 println!("successfully set credentials");
             }
             Err(e) => exit_with_error(e),
         }
     } else {
-        let mut k = String::new();
+        let k = prompt_line("API key (leave blank to ignore): ", false);
         let mut s = String::new();
-        let mut h = String::new();
-        let mut p = String::new();
-
-        print!("API key (leave blank to ignore): ");
-        stdout().flush().expect("unable to flush stdout?");
-        let res = stdin().read_line(&mut k);
-        if let Err(e) = res {
-            exit_with_error(e);
-        }
-        k = k.trim().to_string();
         // If key input was empty, then don't ask for secret
         if !k.is_empty() {
-            print!("API secret (required): ");
-            stdout().flush().expect("unable to flush stdout?");
-            let res = stdin().read_line(&mut s);
-            s = s.trim().to_string();
-            if let Err(e) = res {
-                exit_with_error(e);
-            }
+            s = prompt_line("API secret (required): ", true);
             if s.is_empty() {
                 exit_with_error(
                     "API secret field is required (leave `API key`\
@@ -139,23 +271,11 @@ pub fn login_command(args: &ArgMatches) {
         let k = if !k.is_empty() { Some(k) } else { None };
         let s = if !s.is_empty() { Some(s) } else { None };
 
-        print!("handle or email (leave blank to ignore): ");
-        stdout().flush().expect("unable to flush stdout?");
-        let res = stdin().read_line(&mut h);
-        if let Err(e) = res {
-            exit_with_error(e);
-        }
-        h = h.trim().to_string();
+        let h = prompt_line("handle or email (leave blank to ignore): ", false);
+        let mut p = String::new();
         // If username input was empty, then don't ask for password
         if !h.is_empty() {
-            print!("password (required): ");
-            stdout().flush().expect("unable to flush stdout?");
-            let res = stdin().read_line(&mut p);
-            if let Err(e) = res {
-                exit_with_error(e);
-            }
-            p = p.trim().to_string();
-
+            p = prompt_line("password (required): ", true);
             if p.is_empty() {
                 exit_with_error(
                     "password field is required (leave `handle or \
@@ -168,7 +288,7 @@ pub fn login_command(args: &ArgMatches) {
         let p = if !p.is_empty() { Some(p) } else { None };
 
         println!("saving keys to auth.yml");
-        match auth::set_auth_creds(k, s, h, p) {
+        match auth::set_auth_creds(k, s, h, p, passphrase) {
             Ok(()) => {
                 println!("successfully set credentials");
             }
@@ -179,7 +299,10 @@ pub fn login_command(args: &ArgMatches) {
 
 pub fn config_command(args: &ArgMatches) {
     let d_u = get_optional_arg_of_type(args, "defaultuser");
-    let d_pti = get_optional_arg_of_type(args, "defaultprogramtypeid");
+    let d_pti =
+        get_optional_arg_of_type::<String>(args, "defaultprogramtypeid").map(
+            |s| languages::resolve(&s).unwrap_or_else(|e| exit_with_error(e)),
+        );
 
     if d_u.is_some() || d_pti.is_some() {
         match config::set_config(d_u, d_pti) {
@@ -201,7 +324,11 @@ pub fn config_command(args: &ArgMatches) {
         u = u.trim().to_string();
 
         println!("default program type id (leave blank to ignore): ");
-        print!("ID LANGUAGE\n{}\n: ", crate::PROGRAM_TYPE_ID_HELP);
+        println!("ID LANGUAGE");
+        for l in languages::known() {
+            println!("{:<3}{}", l.id, l.name);
+        }
+        print!(": ");
         stdout().flush().expect("unable to flush stdout?");
         let res = stdin().read_line(&mut p);
         if let Err(e) = res {
@@ -211,9 +338,9 @@ pub fn config_command(args: &ArgMatches) {
 
         let u = if !u.is_empty() { Some(u) } else { None };
         let p: Option<i64> = if !p.is_empty() {
-            match p.parse() {
+            match languages::resolve(&p) {
                 Ok(n) => Some(n),
-                Err(_) => exit_with_error("id must be an integer, aborting"),
+                Err(e) => exit_with_error(e + ", aborting"),
             }
         } else {
             None
@@ -229,25 +356,117 @@ pub fn config_command(args: &ArgMatches) {
     }
 }
 
+pub fn languages_command(args: &ArgMatches) {
+    let fetch = || {
+        let conf = config::get_config_or_default();
+        let (handle, password) = try_get_login_details(args)?;
+        let langs = http_submit::fetch_languages(
+            &get_base_url(args, &conf),
+            &handle,
+            &password,
+        )?;
+        languages::store(&langs)?;
+        Ok::<_, String>(langs)
+    };
+    let langs = match languages::cached() {
+        Some(l) if !args.is_present("refresh") => l,
+        _ => match fetch() {
+            Ok(l) => l,
+            Err(e) if args.is_present("refresh") => exit_with_error(e),
+            Err(e) => {
+                eprintln!(
+                    "unable to fetch languages ({}), using builtin list",
+                    e
+                );
+                languages::builtin()
+            }
+        },
+    };
+    let query = args.value_of("QUERY").unwrap_or_default();
+    for l in languages::search(&langs, query) {
+        println!("{:<3}{}", l.id, l.name);
+    }
+}
+
+pub fn logout_command() {
+    match session::clear() {
+        Ok(true) => println!("removed stored session"),
+        Ok(false) => println!("no stored session"),
+        Err(e) => exit_with_error(e),
+    }
+}
+
+pub fn doctor_command(args: &ArgMatches) {
+    let results = permissions::audit().unwrap_or_else(|e| exit_with_error(e));
+    if results.is_empty() {
+        println!("no credentials stored, nothing to check");
+        return;
+    }
+    let mut n_problems = 0;
+    for (path, problems) in &results {
+        if problems.is_empty() {
+            println!("ok: {}", path.display());
+        }
+        for p in problems {
+            println!("warning: {} {}", path.display(), p);
+            n_problems += 1;
+        }
+    }
+    if n_problems > 0 && args.is_present("strict") {
+        exit_with_error(format!("found {} problem(s)", n_problems));
+    }
+}
+
+pub fn unlock_command() {
+    match auth::export_key() {
+        Ok(Some(k)) => println!("export {}={}", auth::AUTH_KEY_ENV, k),
+        Ok(None) => exit_with_error(
+            "auth.yml isn't encrypted (see `caffeine login --encrypt`)",
+        ),
+        Err(e) => exit_with_error(e),
+    }
+}
+
+pub fn cache_command(matches: &ArgMatches) {
+    match matches.subcommand() {
+        ("clear", Some(_)) => match cache::clear() {
+            Ok(n) => println!("removed {} cached responses", n),
+            Err(e) => exit_with_error(e),
+        },
+        ("stats", Some(_)) => match cache::stats() {
+            Ok(st) => {
+                println!("location: {}", st.dir.display());
+                println!("entries: {}", st.entries);
+                println!("size: {} bytes", st.bytes);
+                for (method, (n, bytes)) in st.methods {
+                    println!(
+                        "  {}: {} entries, {} bytes (ttl {}s)",
+                        method,
+                        n,
+                        bytes,
+                        cache::ttl(&method).as_secs()
+                    );
+                }
+            }
+            Err(e) => exit_with_error(e),
+        },
+        _ => {
+            exit_with_usage(matches);
+        }
+    }
+}
+
 pub fn blogentry_command(matches: &ArgMatches) {
     match matches.subcommand() {
         ("comments", Some(args)) => {
             let i = get_optional_arg_of_type(args, "BLOGENTRYID").unwrap();
             let x = CFBlogEntryCommand::Comments { blog_entry_id: i };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         ("view", Some(args)) => {
             let i = get_optional_arg_of_type(args, "BLOGENTRYID").unwrap();
             let x = CFBlogEntryCommand::View { blog_entry_id: i };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         _ => {
             exit_with_usage(matches);
@@ -255,37 +474,183 @@ pub fn blogentry_command(matches: &ArgMatches) {
     }
 }
 
+/// Fetch the testcases for each of the given problems, which belong to
+/// `target` (else to the problemset). Problems whose testcases couldn't be
+/// fetched are given `None`.
+fn fetch_testcases(
+    args: &ArgMatches,
+    problems: &mut [CFProblem],
+    target: Option<&Target>,
+) -> Vec<Option<Vec<Testcase>>> {
+    let base_url = get_base_url(args, &config::get_config_or_default());
+    problems
+        .iter_mut()
+        .map(|p| testcases::fetch_for_problem(p, target, &base_url).ok())
+        .collect()
+}
+
+/// Print a result containing problems (contest standings or a problemset)
+/// with the testcases of each problem added.
+fn print_with_testcases(
+    args: &ArgMatches,
+    res: &CFResult,
+    tcs: &[Option<Vec<Testcase>>],
+) {
+    // unwrap is probably ok here since serializing errors are very rare
+    let mut v = serde_json::to_value(res).unwrap();
+    testcases::attach(&mut v, tcs);
+    output::print_value(args, &v, Some(output::default_columns(res)));
+}
+
+/// Number of seconds from `now` until contest `id` starts (0 if it has
+/// already started). The start time is used rather than relativeTimeSeconds
+/// since the contest list may come from the cache.
+fn seconds_until_start(
+    contests: &[CFContest],
+    id: i64,
+    now: std::time::SystemTime,
+) -> Option<i64> {
+    let now = now
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    contests
+        .iter()
+        .find(|c| c.id == id)
+        .and_then(|c| c.start_time_seconds)
+        .map(|t| std::cmp::max(0, t - now))
+}
+
+/// Fetch the problems of a contest along with their testcases. If `--wait`
+/// was given, then wait for the contest to start and keep trying until the
+/// testcases for every problem are available.
+fn fetch_contest_problems(
+    args: &ArgMatches,
+    target: &Target,
+) -> Vec<(CFProblem, Vec<Testcase>)> {
+    let i = target.contest_id();
+    let base_url = get_base_url(args, &config::get_config_or_default());
+    let t = get_optional_arg_of_type(args, "timeout").unwrap();
+    let w = args.occurrences_of("wait") > 0;
+    let dt = get_optional_arg_of_type(args, "wait").unwrap();
+
+    // Check start time of contest using contest.list and searching
+    // by contestId (only if --wait flag enabled)
+    let time_to_start = if w {
+        let list_req = CFContestCommand::List { gym: None };
+        match api::request(args, &list_req) {
+            Ok(CFResult::CFContestVec(ref v)) => {
+                seconds_until_start(v, i, std::time::SystemTime::now())
+            }
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    if let Some(t) = time_to_start {
+        eprintln!("sleeping until start ({} seconds from now)", t);
+        std::thread::sleep(std::time::Duration::from_secs(t as u64));
+    }
+
+    let start_instant = std::time::Instant::now();
+
+    // Downloading testcases first requires knowing the list of problem
+    // indices. These are fetched with contest.standings api req.
+    let x = CFContestCommand::Standings {
+        contest_id: i,
+        handles: None,
+        from: Some(1),
+        count: Some(1),
+        room: None,
+        show_unofficial: None,
+    };
+    loop {
+        eprintln!("fetching problems");
+        let mut res = api::request(args, &x);
+        match res {
+            Ok(CFResult::CFContestStandings(ref mut standings)) => {
+                // Continue to fetch testcases for all problems in the
+                // contest until no errors are returned.
+                let mut done = true;
+                let mut problems = vec![];
+                for p in &mut standings.problems {
+                    let testcases = testcases::fetch_for_problem(
+                        p,
+                        Some(target),
+                        &base_url,
+                    );
+                    done &= !w | testcases.is_ok();
+                    if !done {
+                        eprintln!(
+                            "failed to fetch testcases for problem {}",
+                            p.index.as_ref().unwrap()
+                        );
+                        break;
+                    }
+                    problems.push((p.clone(), testcases.unwrap_or_default()));
+                }
+                if done {
+                    return problems;
+                }
+            }
+            Ok(_) => {
+                exit_with_error(
+                    "Incorrectly parsed contest standings \
+                    response object.",
+                );
+            }
+            Err(ApiError::CodeforcesApi(e)) => {
+                // Error returned by Codeforces api probably indicates
+                // that the aren't ready yet. Ignore and keep
+                // looping.
+                if !w {
+                    eprintln!(
+                        "To wait for the contest to start use \
+                        the --wait flag."
+                    );
+                    exit_with_error(e);
+                }
+            }
+            Err(e) => {
+                exit_with_error(e);
+            }
+        }
+        if start_instant.elapsed().as_secs_f32() > t {
+            exit_with_error("timed out waiting for contest");
+        }
+
+        if !w {
+            exit_with_error("unable to fetch problems for contest");
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs_f32(dt));
+        eprintln!(
+            "wasn't able to fetch (all) problems, sleeping and \
+            trying again"
+        );
+    }
+}
+
 pub fn contest_command(matches: &ArgMatches) {
     match matches.subcommand() {
         ("hacks", Some(args)) => {
-            let i = get_optional_arg_of_type(args, "CONTESTID").unwrap();
+            let i = get_contest_id(args);
             let x = CFContestCommand::Hacks { contest_id: i };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         ("list", Some(args)) => {
             let b = args.is_present("gym");
             let x = CFContestCommand::List { gym: Some(b) };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         ("ratingchanges", Some(args)) => {
-            let i = get_optional_arg_of_type(args, "CONTESTID").unwrap();
+            let i = get_contest_id(args);
             let x = CFContestCommand::RatingChanges { contest_id: i };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         ("standings", Some(args)) => {
-            let i = get_optional_arg_of_type(args, "CONTESTID").unwrap();
+            let target = get_contest_target(args);
+            let i = target.contest_id();
             let f = get_optional_arg_of_type(args, "from");
             let n = get_optional_arg_of_type(args, "count");
             let h = get_optional_args_of_type(args, "handles");
@@ -304,23 +669,26 @@ pub fn contest_command(matches: &ArgMatches) {
                 println!("{}", get_from_api_raw(args, &x));
             } else {
                 let mut res = get_from_api(args, &x);
-                if !t {
-                    if let CFResult::CFContestStandings(ref mut standings) = res
-                    {
-                        for p in &mut standings.problems {
-                            let _ = p.fetch_testcases();
-                        }
-                    } else {
-                        exit_with_error(
-                            "something went wrong while parsing response",
-                        );
-                    }
+                if t {
+                    output::print_result(args, &res);
+                } else if let CFResult::CFContestStandings(ref mut standings) =
+                    res
+                {
+                    let tcs = fetch_testcases(
+                        args,
+                        &mut standings.problems,
+                        Some(&target),
+                    );
+                    print_with_testcases(args, &res, &tcs);
+                } else {
+                    exit_with_error(
+                        "something went wrong while parsing response",
+                    );
                 }
-                println!("{}", res);
             }
         }
         ("status", Some(args)) => {
-            let i = get_optional_arg_of_type(args, "CONTESTID").unwrap();
+            let i = get_contest_id(args);
             let h = get_optional_arg_of_type(args, "handle");
             let f = get_optional_arg_of_type(args, "from");
             let n = get_optional_arg_of_type(args, "count");
@@ -330,131 +698,67 @@ pub fn contest_command(matches: &ArgMatches) {
                 from: f,
                 count: n,
             };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
+            print_from_api(args, &x);
+        }
+        ("setup", Some(args)) => {
+            let target = get_contest_target(args);
+            let i = target.contest_id();
+            let dir = get_optional_arg_of_type::<String>(args, "DIR")
+                .unwrap_or_else(|| i.to_string());
+            let conf = config::get_config_or_default();
+            let mut settings = workspace::WorkspaceSettings::from_config(&conf);
+            if let Some(t) = get_optional_arg_of_type(args, "template") {
+                settings.template = Some(t);
+            }
+            let template = match settings.read_template() {
+                Ok(t) => t,
+                Err(e) => exit_with_error(e),
+            };
+            let root = Path::new(&dir);
+            let problems = fetch_contest_problems(args, &target);
+            for (p, tcs) in &problems {
+                let res = workspace::setup_problem(
+                    root,
+                    i,
+                    p,
+                    tcs,
+                    &settings,
+                    template.as_deref(),
+                );
+                if let Err(e) = res {
+                    exit_with_error(e);
+                }
             }
+            let contest = args.value_of("CONTESTID").unwrap();
+            if let Err(e) = workspace::write_info(root, contest) {
+                exit_with_error(e);
+            }
+            println!("{}", root.display());
         }
         ("testcases", Some(args)) => {
-            let i = get_optional_arg_of_type(args, "CONTESTID").unwrap();
-            let t = get_optional_arg_of_type(args, "timeout").unwrap();
-            let w = args.occurrences_of("wait") > 0;
-            let dt = get_optional_arg_of_type(args, "wait").unwrap();
-            let (k, s) = get_api_key_secret(args);
-
-            // Check start time of contest using contest.info and searching
-            // by contestId (only if --wait flag enabled)
-            let time_to_start = if w {
-                let list_req = CFContestCommand::List { gym: None };
-                let res = list_req.get(&k, &s);
-                if let Ok(CFResult::CFContestVec(ref v)) = res {
-                    v.iter().find_map(|contest| {
-                        if contest.id == i {
-                            match contest.relative_time_seconds {
-                                Some(t) => Some(std::cmp::max(0, -t)),
-                                None => None,
-                            }
-                        } else {
-                            None
-                        }
-                    })
-                } else {
-                    None
-                }
+            let problems =
+                fetch_contest_problems(args, &get_contest_target(args));
+            if args.is_present("format") {
+                let v = serde_json::json!(problems
+                    .iter()
+                    .map(|(p, tcs)| serde_json::json!({
+                        "index": p.index,
+                        "name": p.name,
+                        "testcases": tcs,
+                    }))
+                    .collect::<Vec<_>>());
+                output::print_value(args, &v, None);
             } else {
-                None
-            };
-
-            if let Some(t) = time_to_start {
-                eprintln!("sleeping until start ({} seconds from now)", t);
-                std::thread::sleep(std::time::Duration::from_secs(t as u64));
-            }
-
-            let start_instant = std::time::Instant::now();
-
-            // Downloading testcases first requires knowing the list of problem
-            // indices. These are fetched with contest.standings api req.
-            let x = CFContestCommand::Standings {
-                contest_id: i,
-                handles: None,
-                from: Some(1),
-                count: Some(1),
-                room: None,
-                show_unofficial: None,
-            };
-            loop {
-                eprintln!("fetching problems");
-                let mut res = x.get(&k, &s);
-                match res {
-                    Ok(CFResult::CFContestStandings(ref mut standings)) => {
-                        // Continue to fetch testcases for all problems in the
-                        // contest until no errors are returned.
-                        let mut done = true;
-                        for p in &mut standings.problems {
-                            let testcases = p.fetch_testcases();
-                            done &= !w | testcases.is_ok();
-                            if !done {
-                                eprintln!(
-                                    "failed to fetch testcases for problem {}",
-                                    p.index.as_ref().unwrap()
-                                );
-                                break;
-                            }
-                        }
-                        if done {
-                            for p in &standings.problems {
-                                println!(
-                                    "--- NEW PROBLEM ---\n{}",
-                                    p.index.as_ref().unwrap()
-                                );
-                                if let Some(ref testcases) = p.input_testcases {
-                                    for t in testcases {
-                                        println!(
-                                            "+++ NEW TESTCASE +++\n{}\n",
-                                            t
-                                        );
-                                    }
-                                }
-                            }
-                            break;
-                        }
+                for (p, tcs) in &problems {
+                    println!(
+                        "--- NEW PROBLEM ---\n{}",
+                        p.index.as_ref().unwrap()
+                    );
+                    for t in tcs {
+                        println!("+++ NEW TESTCASE +++\n{}\n", t.input);
+                        println!("+++ EXPECTED OUTPUT +++\n{}\n", t.output);
                     }
-                    Ok(_) => {
-                        exit_with_error(
-                            "Incorrectly parsed contest standings \
-                            response object.",
-                        );
-                    }
-                    Err(ApiError::CodeforcesApi(e)) => {
-                        // Error returned by Codeforces api probably indicates
-                        // that the aren't ready yet. Ignore and keep
-                        // looping.
-                        if !w {
-                            eprintln!(
-                                "To wait for the contest to start use \
-                                the --wait flag."
-                            );
-                            exit_with_error(e);
-                        }
-                    }
-                    Err(e) => {
-                        exit_with_error(e);
-                    }
-                }
-                if start_instant.elapsed().as_secs_f32() > t {
-                    exit_with_error("timed out waiting for contest");
                 }
-
-                if !w {
-                    break;
-                }
-
-                std::thread::sleep(std::time::Duration::from_secs_f32(dt));
-                eprintln!(
-                    "wasn't able to fetch (all) problems, sleeping and \
-                    trying again"
-                );
             }
         }
         _ => {
@@ -477,18 +781,17 @@ pub fn problemset_command(matches: &ArgMatches) {
                 println!("{}", get_from_api_raw(args, &x));
             } else {
                 let mut res = get_from_api(args, &x);
-                if !f {
-                    if let CFResult::CFProblemset(ref mut problemset) = res {
-                        for p in &mut problemset.problems {
-                            let _ = p.fetch_testcases();
-                        }
-                    } else {
-                        exit_with_error(
-                            "something went wrong while parsing response",
-                        );
-                    }
+                if f {
+                    output::print_result(args, &res);
+                } else if let CFResult::CFProblemset(ref mut problemset) = res {
+                    let tcs =
+                        fetch_testcases(args, &mut problemset.problems, None);
+                    print_with_testcases(args, &res, &tcs);
+                } else {
+                    exit_with_error(
+                        "something went wrong while parsing response",
+                    );
                 }
-                println!("{}", res);
             }
         }
         ("recentstatus", Some(args)) => {
@@ -498,11 +801,7 @@ pub fn problemset_command(matches: &ArgMatches) {
                 count: n,
                 problemset_name: s,
             };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         _ => {
             exit_with_usage(matches);
@@ -513,11 +812,7 @@ pub fn problemset_command(matches: &ArgMatches) {
 pub fn recentactions_command(args: &ArgMatches) {
     let s = get_optional_arg_of_type(args, "maxcount").unwrap();
     let x = CFRecentActionsCommand { max_count: s };
-    if args.is_present("raw") {
-        println!("{}", get_from_api_raw(args, &x));
-    } else {
-        println!("{}", get_from_api(args, &x))
-    }
+    print_from_api(args, &x);
 }
 
 pub fn user_command(matches: &ArgMatches) {
@@ -544,22 +839,14 @@ pub fn user_command(matches: &ArgMatches) {
                 },
             };
             let x = CFUserCommand::BlogEntries { handle: s };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         ("friends", Some(args)) => {
             let o = args.is_present("onlyonline");
             let x = CFUserCommand::Friends {
                 only_online: Some(o),
             };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         ("info", Some(args)) => {
             let v = match get_optional_args_of_type(args, "HANDLES") {
@@ -583,23 +870,14 @@ pub fn user_command(matches: &ArgMatches) {
                 },
             };
             let x = CFUserCommand::Info { handles: v };
-            if args.is_present("raw") {
-                // TODO remove `usecached` cli flag
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         ("ratedlist", Some(args)) => {
             let o = args.is_present("activeonly");
             let x = CFUserCommand::RatedList {
                 active_only: Some(o),
             };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         ("rating", Some(args)) => {
             let s = match get_optional_arg_of_type(args, "HANDLE") {
@@ -623,11 +901,7 @@ pub fn user_command(matches: &ArgMatches) {
                 },
             };
             let x = CFUserCommand::Rating { handle: s };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         ("status", Some(args)) => {
             let s = match get_optional_arg_of_type(args, "HANDLE") {
@@ -657,11 +931,7 @@ pub fn user_command(matches: &ArgMatches) {
                 from: f,
                 count: n,
             };
-            if args.is_present("raw") {
-                println!("{}", get_from_api_raw(args, &x));
-            } else {
-                println!("{}", get_from_api(args, &x))
-            }
+            print_from_api(args, &x);
         }
         _ => {
             exit_with_usage(matches);
@@ -669,34 +939,418 @@ pub fn user_command(matches: &ArgMatches) {
     }
 }
 
-pub fn submit_command(args: &ArgMatches) {
-    let (handle, password) = get_login_details(args);
-    let c = get_optional_arg_of_type(args, "CONTESTID").unwrap();
-    let p = get_optional_arg_of_type::<String>(args, "PROBLEMID").unwrap();
-    let m = get_optional_arg_of_type(args, "mirror");
-    let l = match get_optional_arg_of_type(args, "programtypeid") {
-        Some(s) => s,
+/// Get the programTypeId given on the command line, falling back to the one
+/// configured for the extension of `file` (`extensions` in config.yml) and
+/// then to the default in config.yml.
+fn try_get_program_type_id(
+    args: &ArgMatches,
+    file: Option<&Path>,
+) -> Result<i64, String> {
+    match get_optional_arg_of_type::<String>(args, "programtypeid") {
+        Some(s) => languages::resolve(&s),
         None => match config::get_config() {
-            Ok(config::Config {
-                default_program_type_id: Some(id),
-                ..
-            }) => id,
-            Err(e) => exit_with_error(
-                "unable to access defaults \
+            Ok(conf) => {
+                let ext = file.and_then(|f| f.extension()?.to_str());
+                let by_ext =
+                    conf.extensions.as_ref().and_then(|m| m.get(ext?).copied());
+                match by_ext.or(conf.default_program_type_id) {
+                    Some(id) => Ok(id),
+                    None => Err("no default program type id (programming \
+                        language) set, either run `caffeine config` to do \
+                        so, or provide one as an argument (see help)"
+                        .to_string()),
+                }
+            }
+            Err(e) => Err("unable to access defaults \
                     in config.yml, specific error: \n\t"
-                    .to_string()
-                    + e,
-            ),
-            _ => exit_with_error(
-                "no default program type id (programming \
-                   language) set, either run `caffeine config` to do so, or \
-                   provide one as an argument (see help)",
-            ),
+                .to_string()
+                + e),
+        },
+    }
+}
+
+fn get_program_type_id(args: &ArgMatches, file: Option<&Path>) -> i64 {
+    match try_get_program_type_id(args, file) {
+        Ok(l) => l,
+        Err(e) => exit_with_error(e),
+    }
+}
+
+/// Directory containing the testcases for a solution: the one given with
+/// `--dir`, else the directory of the solution.
+fn get_testcase_dir(args: &ArgMatches, file: &Path) -> PathBuf {
+    match get_optional_arg_of_type::<String>(args, "dir") {
+        Some(d) => PathBuf::from(d),
+        None => file
+            .parent()
+            .filter(|d| !d.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf(),
+    }
+}
+
+/// Compile a solution and run it against the testcases of `problem` stored in
+/// `dir`, printing a report. Returns whether every testcase passed, or an
+/// error if the solution couldn't be tested (eg. it failed to compile).
+fn run_tests(
+    args: &ArgMatches,
+    file: &Path,
+    problem: &str,
+    dir: &Path,
+    l: i64,
+) -> Result<bool, String> {
+    let conf = config::get_config_or_default();
+    let lang = runner::language(&conf, l)?;
+    let time_limit = get_optional_arg_of_type(args, "timelimit")
+        .or(conf.time_limit)
+        .unwrap_or(runner::DEFAULT_TIME_LIMIT);
+    if !time_limit.is_finite() || time_limit <= 0.0 {
+        return Err("time limit must be a positive number".to_string());
+    }
+    let time_limit = std::time::Duration::from_secs_f64(time_limit);
+
+    let settings = workspace::WorkspaceSettings::from_config(&conf);
+    let testcases = runner::find_testcases(dir, problem, &settings);
+    if testcases.is_empty() {
+        return Err(format!("no testcases found in {}", dir.display()));
+    }
+    let checker = get_optional_arg_of_type(args, "checker");
+    let checker =
+        runner::find_checker(checker, dir, problem, &settings, &conf)?;
+
+    let exe = runner::exe_path(file);
+    if let Some(ref c) = lang.compile {
+        eprintln!("compiling {}", file.display());
+        runner::compile(&runner::command_for(c, file, &exe))?;
+    }
+    let cmd = runner::command_for(&lang.run, file, &exe);
+    let res = run_testcases(&cmd, &testcases, time_limit, &checker);
+    if lang.compile.is_some() {
+        let _ = std::fs::remove_file(&exe);
+    }
+    res
+}
+
+/// Run (and judge) each testcase, see [`run_tests`].
+fn run_testcases(
+    cmd: &str,
+    testcases: &[runner::TestcaseFiles],
+    time_limit: std::time::Duration,
+    checker: &checker::Checker,
+) -> Result<bool, String> {
+    let (mut passed, mut unchecked) = (0, 0);
+    for t in testcases {
+        let input = runner::read_file(&t.input)?;
+        let expected = match t.output {
+            Some(ref o) => Some(runner::read_file(o)?),
+            None => None,
+        };
+        let out = runner::run(cmd, &input, time_limit)?;
+        let (verdict, msg) =
+            runner::judge(&out, t, expected.as_deref(), checker)?;
+        println!(
+            "test {}: {} ({:.2}s)",
+            t.num,
+            verdict,
+            out.elapsed.as_secs_f64()
+        );
+        if let Some(m) = msg {
+            println!("{}", m);
+        }
+        match verdict {
+            runner::Verdict::Ok => passed += 1,
+            runner::Verdict::WrongAnswer => {
+                println!("--- expected ---\n{}", expected.unwrap_or_default());
+                println!("--- got ---\n{}", out.stdout);
+            }
+            runner::Verdict::RuntimeError => {
+                println!("--- exit status ---\n{}", out.status.unwrap());
+                println!("--- stderr ---\n{}", out.stderr);
+            }
+            runner::Verdict::Unchecked => {
+                unchecked += 1;
+                println!("--- got ---\n{}", out.stdout);
+            }
+            runner::Verdict::TimeLimitExceeded => {}
+        }
+    }
+
+    if unchecked > 0 {
+        println!(
+            "passed {}/{} tests ({} unchecked since there's no expected \
+            output)",
+            passed,
+            testcases.len(),
+            unchecked
+        );
+    } else {
+        println!("passed {}/{} tests", passed, testcases.len());
+    }
+    Ok(passed == testcases.len())
+}
+
+pub fn test_command(args: &ArgMatches) {
+    let filename =
+        get_optional_arg_of_type::<String>(args, "FILENAME").unwrap();
+    let file = Path::new(&filename);
+    if !file.is_file() {
+        exit_with_error(format!("{} is not a file", filename));
+    }
+    let l = get_program_type_id(args, Some(file));
+    let dir = get_testcase_dir(args, file);
+    let problem = runner::problem_from_file(file);
+    match run_tests(args, file, &problem, &dir, l) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => exit_with_error(e),
+    }
+}
+
+/// Handle whose submissions are polled after submitting. Since logging in
+/// with an email is allowed, the default user is used in that case.
+fn get_status_handle(login: &str) -> String {
+    if !login.contains('@') {
+        return login.to_string();
+    }
+    match config::get_config() {
+        Ok(config::Config {
+            default_user: Some(u),
+            ..
+        }) => u,
+        _ => exit_with_error(
+            "unable to find handle to check verdict with (logged in with \
+            email), set a default user with `caffeine config`",
+        ),
+    }
+}
+
+/// Positional arguments given to `caffeine submit` (CONTESTID, PROBLEMID and
+/// every FILENAME), in order.
+fn get_submit_positionals(args: &ArgMatches) -> Vec<String> {
+    let mut positional: Vec<String> = ["CONTESTID", "PROBLEMID"]
+        .iter()
+        .filter_map(|n| args.value_of(n))
+        .map(String::from)
+        .collect();
+    if let Some(files) = args.values_of("FILENAME") {
+        positional.extend(files.map(String::from));
+    }
+    positional
+}
+
+/// Problem and filename given to `caffeine submit`. CONTESTID may be a
+/// problem code or url (in which case PROBLEMID is omitted), or both may be
+/// omitted in which case the problem is inferred from the path of FILENAME
+/// (eg. `caffeine submit 1466/b.cpp`).
+fn get_submit_problem(
+    positional: &[String],
+) -> (ProblemId, String, Option<String>) {
+    // CONTESTID is required so there is always at least one value
+    let first = &positional[0];
+    let id = match first.parse::<ProblemId>() {
+        Ok(id) => id,
+        Err(e) if positional.len() > 1 => exit_with_error(e),
+        Err(_) => match workspace::problem_from_path(Path::new(first)) {
+            Some(id) => {
+                // unwrap is fine since inferred problems always have an index
+                let p = id.index.clone().unwrap();
+                eprintln!(
+                    "submitting {} to problem {}{}",
+                    first,
+                    id.contest_id(),
+                    p
+                );
+                return (id, p, Some(first.clone()));
+            }
+            None => exit_with_error(format!(
+                "unable to infer problem from {}, provide CONTESTID and \
+                PROBLEMID",
+                first
+            )),
         },
     };
-    let src = match submit::grab_text_from_stdin() {
+    match (id.index.clone(), &positional[1..]) {
+        (Some(i), []) => (id, i, None),
+        (Some(i), [f]) => (id, i, Some(f.clone())),
+        (Some(_), _) => exit_with_error(
+            "PROBLEMID can't be given when CONTESTID is a problem code or url",
+        ),
+        (None, [i]) => (id, i.clone(), None),
+        (None, [i, f]) => (id, i.clone(), Some(f.clone())),
+        (None, []) => exit_with_error(
+            "PROBLEMID is required unless CONTESTID is a problem code or url \
+            (eg. 1466B)",
+        ),
+        (None, _) => exit_with_error(
+            "more than one FILENAME given, use PROBLEM=FILE pairs to submit \
+            several solutions",
+        ),
+    }
+}
+
+/// Whether a positional argument is a `PROBLEM=FILE` pair (urls containing
+/// query parameters aren't).
+fn is_submit_pair(s: &str) -> bool {
+    s.contains('=') && !s.contains('?')
+}
+
+/// Solutions to submit together, given either as `PROBLEM=FILE` pairs (where
+/// PROBLEM is a problem code or url, or just an index following a CONTESTID,
+/// eg. `caffeine submit 1466 A=a.cpp B=b.cpp`) or as a single workspace
+/// directory containing solutions (eg. `caffeine submit .`). Returns None
+/// when a single solution is being submitted.
+fn get_submit_batch(
+    args: &ArgMatches,
+    positional: &[String],
+) -> Option<Vec<(ProblemId, String, String)>> {
+    let mut jobs = Vec::new();
+    if let [dir] = positional {
+        let root = Path::new(dir);
+        if !root.is_dir() {
+            return None;
+        }
+        let conf = config::get_config_or_default();
+        let settings = workspace::WorkspaceSettings::from_config(&conf);
+        let extensions: Vec<String> = conf
+            .extensions
+            .iter()
+            .flat_map(|m| m.keys().cloned())
+            .collect();
+        let found = workspace::find_solutions(root, &settings, &extensions)
+            .unwrap_or_else(|e| exit_with_error(e));
+        for (id, file) in found {
+            // unwrap is fine since inferred problems always have an index
+            let p = id.index.clone().unwrap();
+            jobs.push((id, p, file.display().to_string()));
+        }
+        if jobs.is_empty() {
+            exit_with_error(format!("no solutions found in {}", dir));
+        }
+    } else {
+        if !positional.iter().any(|s| is_submit_pair(s)) {
+            return None;
+        }
+        let (contest, pairs) = if is_submit_pair(&positional[0]) {
+            (None, positional)
+        } else {
+            match positional[0].parse::<ProblemId>() {
+                Ok(id) if id.index.is_none() => (Some(id), &positional[1..]),
+                Ok(_) => exit_with_error(
+                    "CONTESTID can't be a problem code or url when submitting \
+                    PROBLEM=FILE pairs",
+                ),
+                Err(e) => exit_with_error(e),
+            }
+        };
+        for pair in pairs {
+            if !is_submit_pair(pair) {
+                exit_with_error(format!("expected PROBLEM=FILE, got {}", pair));
+            }
+            // unwrap is fine since pairs contain a '='
+            let (problem, file) = pair.split_once('=').unwrap();
+            let id = match (&contest, target::parse_index(problem)) {
+                (Some(c), Some(i)) => ProblemId {
+                    target: c.target.clone(),
+                    index: Some(i),
+                },
+                _ => match problem.parse::<ProblemId>() {
+                    Ok(id) if id.index.is_some() => id,
+                    Ok(_) => exit_with_error(format!(
+                        "{} is a contest, not a problem",
+                        problem
+                    )),
+                    Err(e) => exit_with_error(e),
+                },
+            };
+            // unwrap is fine since the index was checked above
+            let p = id.index.clone().unwrap();
+            jobs.push((id, p, file.to_string()));
+        }
+    }
+    for (n, (id, p, f)) in jobs.iter().enumerate() {
+        let target = Target::from_args(args, id);
+        let dup = jobs[..n].iter().find(|(other, q, _)| {
+            q == p && Target::from_args(args, other) == target
+        });
+        if let Some((_, _, g)) = dup {
+            exit_with_error(format!(
+                "both {} and {} are solutions to problem {}{}",
+                g,
+                f,
+                id.contest_id(),
+                p
+            ));
+        }
+    }
+    Some(jobs)
+}
+
+fn get_submit_backend(
+    args: &ArgMatches,
+    conf: &config::Config,
+) -> submit::Backend {
+    match get_optional_arg_of_type(args, "backend") {
+        Some(b) => b,
+        None => match conf.submit_backend {
+            Some(ref b) => b.parse().unwrap_or_else(|e| exit_with_error(e)),
+            None => submit::Backend::Chrome,
+        },
+    }
+}
+
+fn get_test_first(args: &ArgMatches, conf: &config::Config) -> bool {
+    !args.is_present("notestfirst")
+        && (args.is_present("testfirst") || conf.test_first.unwrap_or(false))
+}
+
+fn read_solution(filename: &str) -> Result<String, String> {
+    eprintln!("loading {}", filename);
+    let mut f = File::open(Path::new(filename)).map_err(|e| e.to_string())?;
+    let mut buf = String::new();
+    match f.read_to_string(&mut buf) {
+        Ok(n_bytes) if n_bytes > 0 => Ok(buf),
+        Ok(_) => Err("file empty".to_string()),
+        Err(e) => Err(format!("unable to read from file: {}", e)),
+    }
+}
+
+/// Apply the preprocessing configured in config.yml (unless `--no-preprocess`
+/// was given) to a solution submitted in language `l`.
+fn preprocess_solution(
+    args: &ArgMatches,
+    conf: &config::Config,
+    src: String,
+    file: Option<&Path>,
+    l: i64,
+) -> Result<String, String> {
+    if args.is_present("nopreprocess") {
+        return Ok(src);
+    }
+    let language = languages::known()
+        .into_iter()
+        .find(|lang| lang.id == l)
+        .map(|lang| lang.name);
+    Preprocessor::from_config(conf).apply(&src, file, language.as_deref())
+}
+
+pub fn submit_command(args: &ArgMatches) {
+    let positional = get_submit_positionals(args);
+    if let Some(jobs) = get_submit_batch(args, &positional) {
+        submit_batch(args, jobs);
+    }
+    let (handle, password) = get_login_details(args);
+    let (id, p, filename) = get_submit_problem(&positional);
+    let c = id.contest_id();
+    let l = get_program_type_id(args, filename.as_deref().map(Path::new));
+    let conf = config::get_config_or_default();
+    let backend = get_submit_backend(args, &conf);
+    let base_url = get_base_url(args, &conf);
+    let test_first = get_test_first(args, &conf);
+    let stdin_src = submit::grab_text_from_stdin();
+    let from_stdin = stdin_src.is_some();
+    let src = match stdin_src {
         Some(s) => {
-            if args.is_present("FILENAME") {
+            if filename.is_some() {
                 eprintln!(
                     "FILENAME (cli option) is being ignored since text \
                     was found on stdin"
@@ -704,45 +1358,278 @@ pub fn submit_command(args: &ArgMatches) {
             }
             s
         }
-        None => {
-            if args.is_present("FILENAME") {
-                let filename =
-                    get_optional_arg_of_type::<String>(args, "FILENAME")
-                        .unwrap();
-                eprintln!("loading {}", filename);
-                match File::open(Path::new(&filename)) {
-                    Ok(mut f) => {
-                        let mut buf = String::new();
-                        match f.read_to_string(&mut buf) {
-                            Ok(n_bytes) => {
-                                if n_bytes > 0 {
-                                    buf
-                                } else {
-                                    exit_with_error("file empty")
-                                }
-                            }
-                            Err(e) => exit_with_error(format!(
-                                "unable to read \
-                                    from file: {}",
-                                e
-                            )),
-                        }
-                    }
+        None => match filename {
+            Some(ref filename) => {
+                read_solution(filename).unwrap_or_else(|e| exit_with_error(e))
+            }
+            None => exit_with_error(
+                "no file provided with --file or from stdin \
+                    pipe",
+            ),
+        },
+    };
+    if test_first {
+        match filename {
+            Some(ref f) if !from_stdin => {
+                let file = Path::new(f);
+                let dir = get_testcase_dir(args, file);
+                match run_tests(args, file, &p, &dir, l) {
+                    Ok(true) => {}
+                    Ok(false) => exit_with_error(
+                        "not submitting since not every sample passed",
+                    ),
                     Err(e) => exit_with_error(e),
                 }
-            } else {
-                exit_with_error(
-                    "no file provided with --file or from stdin \
-                    pipe",
-                )
             }
+            _ => exit_with_error(
+                "testing before submitting requires a FILENAME (use \
+                --no-test-first to submit from stdin)",
+            ),
         }
+    }
+    // The latest submission is noted before submitting so that the new one
+    // can be told apart from older submissions to the same problem.
+    let target = Target::from_args(args, &id);
+    let wait = if args.is_present("wait") {
+        let settings = verdict::WaitSettings::from_args(args);
+        let h = get_status_handle(&handle);
+        let last = verdict::latest_submission_id(args, c, &h);
+        Some((h, last, settings))
+    } else {
+        None
     };
-    let res = submit::submit_from_string(&src, c, &p, &handle, &password, l, m);
+    let file = filename.as_deref().filter(|_| !from_stdin).map(Path::new);
+    let src = preprocess_solution(args, &conf, src, file, l)
+        .unwrap_or_else(|e| exit_with_error(e));
+    let res = submit::submitter(backend, &base_url, &handle, &password)
+        .map_err(SubmitError::from)
+        .and_then(|mut s| s.submit(&src, &target, &p, l));
     match res {
         Ok(()) => {
             eprintln!("successful submission");
         }
-        Err(e) => exit_with_error(e),
+        Err(e) => exit_with_submit_error(&e),
+    }
+    if let Some((h, last, settings)) = wait {
+        match verdict::wait_for_verdict(args, c, &p, &h, last, &settings) {
+            Ok(s) => std::process::exit(verdict::report(&s)),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+    }
+}
+
+/// Submit several solutions in a single session. A solution which can't be
+/// submitted (eg. because its samples failed) doesn't stop the others from
+/// being submitted, the result of each is reported at the end.
+fn submit_batch(
+    args: &ArgMatches,
+    jobs: Vec<(ProblemId, String, String)>,
+) -> ! {
+    let (handle, password) = get_login_details(args);
+    let conf = config::get_config_or_default();
+    let backend = get_submit_backend(args, &conf);
+    let base_url = get_base_url(args, &conf);
+    let test_first = get_test_first(args, &conf);
+    let settings = verdict::WaitSettings::from_args(args);
+    let status_handle = if args.is_present("wait") {
+        Some(get_status_handle(&handle))
+    } else {
+        None
+    };
+
+    let mut submitter = None;
+    // Error logging in (or staying logged in), after which the rest of the
+    // solutions aren't attempted rather than logging in again for each one
+    // (which would get the account throttled).
+    let mut session_error = None;
+    let mut results = Vec::new();
+    for (id, p, filename) in &jobs {
+        let name = format!("{}{}", id.contest_id(), p);
+        if session_error.is_some() {
+            results.push((name, filename, None));
+            continue;
+        }
+        eprintln!("submitting {} to problem {}", filename, name);
+        let file = Path::new(filename);
+        let mut logging_in = false;
+        let res: Result<Option<Option<i64>>, SubmitError> = (|| {
+            let l = try_get_program_type_id(args, Some(file))?;
+            let src = read_solution(filename)?;
+            if test_first {
+                let dir = get_testcase_dir(args, file);
+                if !run_tests(args, file, p, &dir, l)? {
+                    return Err(
+                        "not submitting since not every sample passed".into()
+                    );
+                }
+            }
+            let src = preprocess_solution(args, &conf, src, Some(file), l)?;
+            let target = Target::from_args(args, id);
+            let last = status_handle.as_ref().map(|h| {
+                verdict::latest_submission_id(args, id.contest_id(), h)
+            });
+            if submitter.is_none() {
+                logging_in = true;
+                submitter = Some(submit::submitter(
+                    backend, &base_url, &handle, &password,
+                )?);
+                logging_in = false;
+            }
+            // unwrap is fine since the submitter was just created
+            submitter.as_mut().unwrap().submit(&src, &target, p, l)?;
+            Ok(last)
+        })();
+        match res {
+            Ok(_) => eprintln!("successful submission"),
+            Err(ref e) => eprintln!("{}", e),
+        }
+        if let Err(ref e) = res {
+            if logging_in || e.is_session_error() {
+                session_error = Some(e.exit_code());
+            }
+        }
+        results.push((name, filename, Some(res)));
+    }
+    // the browser (if any) is closed before waiting for verdicts
+    drop(submitter);
+
+    let failed = results
+        .iter()
+        .filter(|(_, _, r)| r.as_ref().is_some_and(|r| r.is_err()))
+        .count();
+    let skipped = results.iter().filter(|(_, _, r)| r.is_none()).count();
+    for (name, filename, res) in &results {
+        match res {
+            Some(Ok(_)) => println!("{} ({}): submitted", name, filename),
+            Some(Err(e)) => println!("{} ({}): {}", name, filename, e),
+            None => println!("{} ({}): not attempted", name, filename),
+        }
+    }
+
+    let mut code = 0;
+    if let Some(ref h) = status_handle {
+        for ((id, p, _), (name, _, res)) in jobs.iter().zip(&results) {
+            if let Some(Ok(Some(last))) = res {
+                let s = verdict::wait_for_verdict(
+                    args,
+                    id.contest_id(),
+                    p,
+                    h,
+                    *last,
+                    &settings,
+                );
+                let c = match s {
+                    Ok(s) => {
+                        println!("{}: {}", name, verdict::summary(&s));
+                        // unwrap is fine since only judged submissions are
+                        // returned
+                        verdict::exit_code(s.verdict.as_ref().unwrap())
+                    }
+                    Err(e) => {
+                        println!("{}: {}", name, e);
+                        e.exit_code()
+                    }
+                };
+                if code == 0 {
+                    code = c;
+                }
+            }
+        }
+    }
+    if let Some(c) = session_error {
+        eprintln!(
+            "Error: stopped after failing to log in, {} of {} solutions \
+            failed to submit and {} weren't attempted",
+            failed,
+            results.len(),
+            skipped
+        );
+        std::process::exit(c);
+    }
+    // the status is that of the first solution which failed to submit
+    if let Some((_, _, Some(Err(e)))) = results
+        .iter()
+        .find(|(_, _, r)| r.as_ref().is_some_and(|r| r.is_err()))
+    {
+        eprintln!(
+            "Error: {} of {} solutions failed to submit",
+            failed,
+            results.len()
+        );
+        std::process::exit(e.exit_code());
+    }
+    std::process::exit(code);
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    fn testcase(
+        num: usize,
+        input: &str,
+        output: Option<&str>,
+    ) -> runner::TestcaseFiles {
+        let dir = testutil::proj_dirs().cache_dir().to_path_buf();
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |ext: &str, s: &str| {
+            let path = dir.join(format!("a{}.{}", num, ext));
+            std::fs::write(&path, s).unwrap();
+            path
+        };
+        runner::TestcaseFiles {
+            num,
+            input: write("in", input),
+            output: output.map(|o| write("out", o)),
+        }
+    }
+
+    #[test]
+    fn unchecked_testcases_dont_pass() {
+        let _dirs = testutil::isolated_dirs();
+        let limit = std::time::Duration::from_secs(2);
+        let checker = checker::Checker::default();
+        let passing = || testcase(1, "1 2\n", Some("1 2\n"));
+        let failing = || testcase(2, "1 2\n", Some("3\n"));
+        let unchecked = || testcase(3, "1 2\n", None);
+        for (testcases, expected) in [
+            (vec![passing()], true),
+            (vec![passing(), failing()], false),
+            (vec![passing(), unchecked()], false),
+            (vec![unchecked()], false),
+        ] {
+            assert_eq!(
+                run_testcases("cat", &testcases, limit, &checker),
+                Ok(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn waits_for_start_time() {
+        let contests: Vec<CFContest> =
+            serde_json::from_value(serde_json::json!([
+                {"id": 1, "name": "a", "type": "CF", "phase": "BEFORE",
+                 "frozen": false, "durationSeconds": 7200,
+                 "startTimeSeconds": 1000, "relativeTimeSeconds": -3600},
+                {"id": 2, "name": "b", "type": "CF", "phase": "FINISHED",
+                 "frozen": false, "durationSeconds": 7200,
+                 "startTimeSeconds": 100},
+                {"id": 3, "name": "c", "type": "CF", "phase": "BEFORE",
+                 "frozen": false, "durationSeconds": 7200},
+            ]))
+            .unwrap();
+        let at =
+            |secs| std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        // relativeTimeSeconds (possibly cached) is ignored
+        assert_eq!(seconds_until_start(&contests, 1, at(900)), Some(100));
+        assert_eq!(seconds_until_start(&contests, 1, at(1000)), Some(0));
+        assert_eq!(seconds_until_start(&contests, 2, at(900)), Some(0));
+        assert_eq!(seconds_until_start(&contests, 3, at(900)), None);
+        assert_eq!(seconds_until_start(&contests, 4, at(900)), None);
     }
 }
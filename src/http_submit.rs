@@ -0,0 +1,770 @@
+use rand::Rng;
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::header::{HeaderValue, COOKIE, LOCATION, SET_COOKIE};
+use reqwest::redirect::Policy;
+use reqwest::{StatusCode, Url};
+use select::document::Document;
+use select::predicate::{Attr, Class, Name, Predicate};
+use std::collections::BTreeMap;
+
+use crate::languages::Language;
+use crate::session as stored;
+use crate::submit::Submitter;
+use crate::submit_error::{self, SubmitError};
+use crate::target::Target;
+
+const MAX_REDIRECTS: usize = 10;
+const USER_AGENT: &str = concat!("caffeine/", env!("CARGO_PKG_VERSION"));
+
+/// A page fetched by a [`Session`], after following any redirects.
+pub struct Page {
+    pub url: Url,
+    pub body: String,
+}
+
+/// Minimal HTTP session which keeps track of cookies and follows redirects
+/// itself (so that cookies set whilst redirecting aren't lost). Cookies are
+/// only sent to (and accepted from) the site at `base_url`, so that the
+/// session isn't leaked by a redirect to another site.
+pub struct Session {
+    client: Client,
+    base_url: Url,
+    pub cookies: BTreeMap<String, String>,
+}
+
+impl Session {
+    pub fn new(base_url: &str) -> Result<Session, String> {
+        let base_url = Url::parse(base_url)
+            .map_err(|e| format!("invalid base url {}: {}", base_url, e))?;
+        let client = Client::builder()
+            .redirect(Policy::none())
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| format!("http: {}", e))?;
+        Ok(Session {
+            client,
+            base_url,
+            cookies: BTreeMap::new(),
+        })
+    }
+
+    pub fn get(&mut self, url: &str) -> Result<Page, SubmitError> {
+        let req = self.client.get(url);
+        self.send(req)
+    }
+
+    pub fn post_form(
+        &mut self,
+        url: &str,
+        form: &[(&str, &str)],
+    ) -> Result<Page, SubmitError> {
+        let req = self.client.post(url).form(form);
+        self.send(req)
+    }
+
+    fn send(&mut self, req: RequestBuilder) -> Result<Page, SubmitError> {
+        let mut req = req;
+        for _ in 0..MAX_REDIRECTS {
+            let mut request =
+                req.build().map_err(|e| format!("http: {}", e))?;
+            let same_site = request.url().origin() == self.base_url.origin();
+            if same_site && !self.cookies.is_empty() {
+                let cookies = HeaderValue::from_str(&self.cookie_header())
+                    .map_err(|e| format!("http: {}", e))?;
+                request.headers_mut().insert(COOKIE, cookies);
+            }
+            let res = self
+                .client
+                .execute(request)
+                .map_err(|e| format!("http: {}", e))?;
+            if same_site {
+                for c in res.headers().get_all(SET_COOKIE) {
+                    if let Ok(c) = c.to_str() {
+                        self.store_cookie(c);
+                    }
+                }
+            }
+            let location = res
+                .headers()
+                .get(LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .filter(|_| res.status().is_redirection())
+                .and_then(|l| res.url().join(l).ok());
+            match location {
+                Some(next) => req = self.client.get(next),
+                None => {
+                    let url = res.url().clone();
+                    let status = res.status();
+                    let mitigated = res
+                        .headers()
+                        .get("cf-mitigated")
+                        .is_some_and(|v| v.as_bytes() == b"challenge");
+                    let body =
+                        res.text().map_err(|e| format!("http: {}", e))?;
+                    if is_challenge(status, mitigated, &body) {
+                        return Err(SubmitError::Challenge);
+                    }
+                    if status.is_server_error() {
+                        let e = format!("http: {} from {}", status, url);
+                        return Err(e.into());
+                    }
+                    return Ok(Page { url, body });
+                }
+            }
+        }
+        Err("http: too many redirects".into())
+    }
+
+    fn cookie_header(&self) -> String {
+        self.cookies
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    fn store_cookie(&mut self, header: &str) {
+        let pair = header.split(';').next().unwrap_or_default();
+        if let Some(i) = pair.find('=') {
+            let (name, value) = (pair[..i].trim(), pair[i + 1..].trim());
+            if value.is_empty() {
+                self.cookies.remove(name);
+            } else {
+                self.cookies.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+}
+
+/// Fields sent along with every form on Codeforces (normally generated by
+/// javascript in the browser).
+struct Fingerprint {
+    ftaa: String,
+    bfaa: String,
+}
+
+impl Fingerprint {
+    fn new() -> Fingerprint {
+        const ALPHANUMERIC: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+        let mut rng = rand::thread_rng();
+        let ftaa = (0..18)
+            .map(|_| ALPHANUMERIC[rng.gen_range(0..ALPHANUMERIC.len())] as char)
+            .collect();
+        let bfaa = hex::encode(rng.gen::<[u8; 16]>());
+        Fingerprint { ftaa, bfaa }
+    }
+}
+
+/// Whether a response is a Cloudflare challenge, which are always shown with
+/// a 403 or 503 status (and a `cf-mitigated: challenge` header, or at least
+/// the title of a challenge page).
+fn is_challenge(status: StatusCode, mitigated: bool, body: &str) -> bool {
+    if status != StatusCode::FORBIDDEN
+        && status != StatusCode::SERVICE_UNAVAILABLE
+    {
+        return false;
+    }
+    mitigated
+        || Document::from(body)
+            .find(Name("title"))
+            .next()
+            .is_some_and(|t| submit_error::is_challenge_title(&t.text()))
+}
+
+fn csrf_token(body: &str) -> Option<String> {
+    let document = Document::from(body);
+    let input = document
+        .find(Name("input").and(Attr("name", "csrf_token")))
+        .find_map(|n| n.attr("value").map(String::from));
+    input.or_else(|| {
+        document
+            .find(Name("meta").and(Attr("name", "X-Csrf-Token")))
+            .find_map(|n| n.attr("content").map(String::from))
+    })
+}
+
+/// Text of the first (non-empty) error shown on a page.
+fn page_error(body: &str) -> Option<String> {
+    Document::from(body)
+        .find(Class("error"))
+        .map(|n| n.text().trim().to_string())
+        .find(|s| !s.is_empty())
+}
+
+fn save_session(session: &Session, base_url: &str, handle: &str) {
+    if let Err(e) = stored::save(base_url, handle, &session.cookies) {
+        eprintln!("{}", e);
+    }
+}
+
+fn login(
+    session: &mut Session,
+    fp: &Fingerprint,
+    base_url: &str,
+    handle: &str,
+    password: &str,
+) -> Result<(), SubmitError> {
+    let enterurl = base_url.to_string() + "enter";
+    let page = session.get(&enterurl)?;
+    let csrf = csrf_token(&page.body)
+        .ok_or("unable to find csrf_token on login page")?;
+    let page = session.post_form(
+        &enterurl,
+        &[
+            ("csrf_token", &csrf),
+            ("action", "enter"),
+            ("ftaa", &fp.ftaa),
+            ("bfaa", &fp.bfaa),
+            ("handleOrEmail", handle),
+            ("password", password),
+            ("_tta", "176"),
+            ("remember", "on"),
+        ],
+    )?;
+    // A failed login shows the login page again rather than redirecting.
+    if stored::is_login_page(&page.url) {
+        Err(SubmitError::LoginFailed)
+    } else {
+        Ok(())
+    }
+}
+
+/// Fetch a page which requires being logged in. The current session (else
+/// the stored one) is reused if there is one, pages which require logging in
+/// redirect to /enter once it has expired.
+fn get_logged_in(
+    session: &mut Session,
+    fp: &Fingerprint,
+    base_url: &str,
+    url: &str,
+    handle: &str,
+    password: &str,
+) -> Result<Page, SubmitError> {
+    let mut restored = false;
+    if session.cookies.is_empty() {
+        if let Some(cookies) = stored::load(base_url, handle) {
+            session.cookies = cookies;
+            restored = true;
+        }
+    }
+    if !session.cookies.is_empty() {
+        let page = session.get(url)?;
+        if !stored::is_login_page(&page.url) {
+            if restored {
+                eprintln!("reusing stored session");
+            }
+            return Ok(page);
+        }
+        eprintln!(
+            "{} expired",
+            if restored {
+                "stored session"
+            } else {
+                "session"
+            }
+        );
+        session.cookies.clear();
+    }
+    eprintln!("attempting login");
+    login(session, fp, base_url, handle, password)?;
+    eprintln!("login successful");
+    save_session(session, base_url, handle);
+    session.get(url)
+}
+
+/// Fetch the languages which solutions can be submitted in from the options
+/// of the programTypeId select on the problemset's submit page.
+pub fn fetch_languages(
+    base_url: &str,
+    handle: &str,
+    password: &str,
+) -> Result<Vec<Language>, String> {
+    let mut session = Session::new(base_url)?;
+    let fp = Fingerprint::new();
+    let url = format!("{}problemset/submit", base_url);
+    let page =
+        get_logged_in(&mut session, &fp, base_url, &url, handle, password)
+            .map_err(|e| e.to_string())?;
+    let langs: Vec<Language> = Document::from(page.body.as_str())
+        .find(
+            Name("select")
+                .and(Attr("name", "programTypeId"))
+                .descendant(Name("option")),
+        )
+        .filter_map(|n| {
+            Some(Language {
+                id: n.attr("value")?.trim().parse().ok()?,
+                name: n.text().trim().to_string(),
+            })
+        })
+        .collect();
+    if langs.is_empty() {
+        Err("unable to find any languages on submit page".to_string())
+    } else {
+        Ok(langs)
+    }
+}
+
+/// Submits solutions using plain HTTP requests rather than a browser.
+pub struct HttpSubmitter {
+    session: Session,
+    fp: Fingerprint,
+    base_url: String,
+    handle: String,
+    password: String,
+}
+
+impl HttpSubmitter {
+    pub fn new(
+        base_url: &str,
+        handle: &str,
+        password: &str,
+    ) -> Result<HttpSubmitter, String> {
+        Ok(HttpSubmitter {
+            session: Session::new(base_url)?,
+            fp: Fingerprint::new(),
+            base_url: base_url.to_string(),
+            handle: handle.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
+
+impl Submitter for HttpSubmitter {
+    fn submit(
+        &mut self,
+        src: &str,
+        target: &Target,
+        problem_index: &str,
+        program_type_id: i64,
+    ) -> Result<(), SubmitError> {
+        let (session, fp) = (&mut self.session, &self.fp);
+        let base_url = self.base_url.as_str();
+
+        let submiturl = target.submit_url(base_url);
+        let page = get_logged_in(
+            session,
+            fp,
+            base_url,
+            &submiturl,
+            &self.handle,
+            &self.password,
+        )?;
+        let csrf = csrf_token(&page.body)
+            .ok_or("unable to find csrf_token on submit page")?;
+        let (field, value) = target.problem_field(problem_index);
+        let type_id = program_type_id.to_string();
+        let page = session.post_form(
+            &format!("{}?csrf_token={}", submiturl, csrf),
+            &[
+                ("csrf_token", &csrf),
+                ("ftaa", &fp.ftaa),
+                ("bfaa", &fp.bfaa),
+                ("action", "submitSolutionFormSubmitted"),
+                (field, &value),
+                ("programTypeId", &type_id),
+                ("source", src),
+                ("tabSize", "4"),
+                ("sourceFile", ""),
+                ("_tta", "176"),
+            ],
+        )?;
+        save_session(session, base_url, &self.handle);
+        if target.is_success_url(base_url, &page.url) {
+            Ok(())
+        } else if stored::is_login_page(&page.url) {
+            Err(SubmitError::LoginRequired)
+        } else {
+            Err(SubmitError::from_message(
+                &page_error(&page.body)
+                    .unwrap_or_else(|| "error on submissions page".to_string()),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// A request received by the stand-in server.
+    #[derive(Clone, Debug)]
+    struct Request {
+        method: String,
+        /// Path including the query.
+        path: String,
+        cookies: BTreeMap<String, String>,
+        form: BTreeMap<String, String>,
+    }
+
+    struct Response {
+        status: u16,
+        headers: Vec<(&'static str, String)>,
+        body: String,
+    }
+
+    fn page(body: &str) -> Response {
+        Response {
+            status: 200,
+            headers: Vec::new(),
+            body: format!("<html><body>{}</body></html>", body),
+        }
+    }
+
+    fn redirect(to: &str) -> Response {
+        Response {
+            status: 302,
+            headers: vec![("Location", to.to_string())],
+            body: String::new(),
+        }
+    }
+
+    fn with_cookie(mut res: Response, cookie: &str) -> Response {
+        res.headers
+            .push(("Set-Cookie", format!("{}; Path=/; HttpOnly", cookie)));
+        res
+    }
+
+    fn decode(s: &str) -> String {
+        let bytes = s.replace('+', " ").into_bytes();
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' && i + 2 < bytes.len() {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                out.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 3;
+            } else {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    fn read_request(stream: &mut impl Read) -> Request {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap().to_string();
+        let path = parts.next().unwrap().to_string();
+        let mut cookies = BTreeMap::new();
+        let mut len = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header.split_at(header.find(':').unwrap());
+            let value = value[1..].trim();
+            match name.to_lowercase().as_str() {
+                "content-length" => len = value.parse().unwrap(),
+                "cookie" => {
+                    for c in value.split(';').filter(|c| c.contains('=')) {
+                        let (k, v) = c.split_at(c.find('=').unwrap());
+                        cookies
+                            .insert(k.trim().to_string(), v[1..].to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body).unwrap();
+        let form = String::from_utf8(body)
+            .unwrap()
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let (k, v) = p.split_at(p.find('=').unwrap_or(p.len()));
+                (decode(k), decode(v.trim_start_matches('=')))
+            })
+            .collect();
+        Request {
+            method,
+            path,
+            cookies,
+            form,
+        }
+    }
+
+    /// Serve requests with `handler` on a local port, returning the base url
+    /// and every request received.
+    fn serve<F>(handler: F) -> (String, Arc<Mutex<Vec<Request>>>)
+    where
+        F: Fn(&Request) -> Response + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let requests = Arc::clone(&log);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let req = read_request(&mut stream);
+                let res = handler(&req);
+                log.lock().unwrap().push(req);
+                let mut head = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\n\
+                    Connection: close\r\n",
+                    res.status,
+                    res.body.len()
+                );
+                for (name, value) in res.headers {
+                    head += &format!("{}: {}\r\n", name, value);
+                }
+                let _ = write!(stream, "{}\r\n{}", head, res.body);
+            }
+        });
+        (base_url, requests)
+    }
+
+    const LOGIN_CSRF: &str = "0123login";
+    const SUBMIT_CSRF: &str = "4567submit";
+
+    fn csrf_html(token: &str, in_meta: bool) -> String {
+        if in_meta {
+            format!("<meta name=\"X-Csrf-Token\" content=\"{}\"/>", token)
+        } else {
+            format!(
+                "<form><input type=\"hidden\" name=\"csrf_token\" \
+                value=\"{}\"/></form>",
+                token
+            )
+        }
+    }
+
+    /// A stand-in for the login and submit pages of Codeforces. The session
+    /// cookie is replaced after logging in and a second cookie is set whilst
+    /// redirecting, both of which the submit page requires.
+    fn site(
+        login_meta: bool,
+        submit_meta: bool,
+    ) -> impl Fn(&Request) -> Response {
+        move |req| {
+            let logged_in = req.cookies.get("JSESSIONID").map(String::as_str)
+                == Some("user")
+                && req.cookies.contains_key("39ce7");
+            let path = req.path.split('?').next().unwrap();
+            match (req.method.as_str(), path) {
+                ("GET", "/enter") => with_cookie(
+                    page(&csrf_html(LOGIN_CSRF, login_meta)),
+                    "JSESSIONID=anonymous",
+                ),
+                ("POST", "/enter") => {
+                    let field = |k: &str| req.form.get(k).map(String::as_str);
+                    let ok = req.cookies.contains_key("JSESSIONID")
+                        && field("csrf_token") == Some(LOGIN_CSRF)
+                        && field("handleOrEmail") == Some("thud")
+                        && field("password") == Some("p@ss \"word\"");
+                    if ok {
+                        with_cookie(redirect("/"), "JSESSIONID=user")
+                    } else {
+                        redirect("/enter")
+                    }
+                }
+                ("GET", "/") => {
+                    with_cookie(redirect("/profile/thud"), "39ce7=remember")
+                }
+                ("GET", "/profile/thud") => page("thud"),
+                (_, "/contest/1466/submit") if !logged_in => {
+                    redirect("/enter?back=%2Fcontest%2F1466%2Fsubmit")
+                }
+                ("GET", "/contest/1466/submit") => {
+                    page(&csrf_html(SUBMIT_CSRF, submit_meta))
+                }
+                ("POST", "/contest/1466/submit") => {
+                    let source = req.form.get("source").unwrap();
+                    if req.form.get("csrf_token").map(String::as_str)
+                        != Some(SUBMIT_CSRF)
+                    {
+                        page("<span class=\"error\">Invalid csrf</span>")
+                    } else if source.contains("duplicate") {
+                        page(
+                            "<span class=\"error for__source\">\n  You have \
+                            submitted exactly the same code before\n</span>",
+                        )
+                    } else {
+                        redirect("/contest/1466/my")
+                    }
+                }
+                ("GET", "/contest/1466/my") => page("submissions"),
+                _ => Response {
+                    status: 404,
+                    headers: Vec::new(),
+                    body: String::new(),
+                },
+            }
+        }
+    }
+
+    fn submit(
+        base_url: &str,
+        password: &str,
+        src: &str,
+    ) -> Result<(), SubmitError> {
+        HttpSubmitter::new(base_url, "thud", password)
+            .unwrap()
+            .submit(src, &Target::Contest(1466), "B", 54)
+    }
+
+    fn posts(log: &Arc<Mutex<Vec<Request>>>, path: &str) -> Vec<Request> {
+        log.lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.method == "POST" && r.path.starts_with(path))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn submits_with_csrf_from_input_or_meta() {
+        let _dirs = testutil::isolated_dirs();
+        for &(login_meta, submit_meta) in &[(false, true), (true, false)] {
+            let (base_url, log) = serve(site(login_meta, submit_meta));
+            submit(&base_url, "p@ss \"word\"", "int main() {}\n").unwrap();
+            let sent = posts(&log, "/contest/1466/submit");
+            assert_eq!(sent.len(), 1);
+            let form = &sent[0].form;
+            assert_eq!(form["csrf_token"], SUBMIT_CSRF);
+            assert_eq!(form["submittedProblemIndex"], "B");
+            assert_eq!(form["programTypeId"], "54");
+            assert_eq!(form["source"], "int main() {}\n");
+        }
+    }
+
+    #[test]
+    fn keeps_cookies_set_whilst_redirecting() {
+        let _dirs = testutil::isolated_dirs();
+        let (base_url, log) = serve(site(false, true));
+        submit(&base_url, "p@ss \"word\"", "int main() {}\n").unwrap();
+        let sent = posts(&log, "/contest/1466/submit");
+        assert_eq!(sent[0].cookies["JSESSIONID"], "user");
+        assert_eq!(sent[0].cookies["39ce7"], "remember");
+        // the redirected request after logging in carried the new session
+        let log = log.lock().unwrap();
+        let profile = log.iter().find(|r| r.path == "/profile/thud").unwrap();
+        assert_eq!(profile.cookies["JSESSIONID"], "user");
+    }
+
+    #[test]
+    fn cookies_stay_on_the_site() {
+        // another site, which redirects back to the site
+        let site_url = Arc::new(Mutex::new(String::new()));
+        let back = Arc::clone(&site_url);
+        let (elsewhere, elsewhere_log) = serve(move |_| {
+            let back = back.lock().unwrap().clone() + "back";
+            with_cookie(redirect(&back), "JSESSIONID=injected")
+        });
+        let (base_url, log) = serve(move |req| match req.path.as_str() {
+            "/" => with_cookie(page(""), "JSESSIONID=user"),
+            "/away" => redirect(&elsewhere),
+            _ => page(""),
+        });
+        *site_url.lock().unwrap() = base_url.clone();
+
+        let mut session = Session::new(&base_url).unwrap();
+        session.get(&base_url).unwrap();
+        let page = session.get(&(base_url.clone() + "away")).unwrap();
+        assert_eq!(page.url.as_str(), base_url.clone() + "back");
+        let elsewhere_log = elsewhere_log.lock().unwrap();
+        assert_eq!(elsewhere_log.len(), 1);
+        assert!(elsewhere_log[0].cookies.is_empty());
+        let log = log.lock().unwrap();
+        let back = log.iter().find(|r| r.path == "/back").unwrap();
+        assert_eq!(back.cookies["JSESSIONID"], "user");
+        assert_eq!(session.cookies["JSESSIONID"], "user");
+    }
+
+    #[test]
+    fn failed_login_returns_to_enter() {
+        let _dirs = testutil::isolated_dirs();
+        let (base_url, log) = serve(site(false, true));
+        let res = submit(&base_url, "wrong", "int main() {}\n");
+        assert!(matches!(res, Err(SubmitError::LoginFailed)), "{:?}", res);
+        assert!(posts(&log, "/contest/1466/submit").is_empty());
+    }
+
+    #[test]
+    fn surfaces_page_error() {
+        let _dirs = testutil::isolated_dirs();
+        let (base_url, _) = serve(site(false, true));
+        let res = submit(&base_url, "p@ss \"word\"", "// duplicate\n");
+        match res {
+            Err(SubmitError::Duplicate(msg)) => assert_eq!(
+                msg,
+                "You have submitted exactly the same code before"
+            ),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn reuses_stored_session() {
+        let _dirs = testutil::isolated_dirs();
+        let (base_url, log) = serve(site(false, true));
+        submit(&base_url, "p@ss \"word\"", "int main() {}\n").unwrap();
+        submit(&base_url, "p@ss \"word\"", "int main() {}\n").unwrap();
+        assert_eq!(posts(&log, "/enter").len(), 1);
+        assert_eq!(posts(&log, "/contest/1466/submit").len(), 2);
+    }
+
+    #[test]
+    fn detects_challenges() {
+        let _dirs = testutil::isolated_dirs();
+        let (base_url, _) = serve(|_| Response {
+            status: 403,
+            headers: vec![("cf-mitigated", "challenge".to_string())],
+            body: "<html><body></body></html>".to_string(),
+        });
+        let res = submit(&base_url, "p@ss \"word\"", "int main() {}\n");
+        assert!(matches!(res, Err(SubmitError::Challenge)), "{:?}", res);
+
+        let (base_url, _) = serve(|_| Response {
+            status: 503,
+            headers: Vec::new(),
+            body: "<html><head><title>Just a moment...</title></head></html>"
+                .to_string(),
+        });
+        let res = submit(&base_url, "p@ss \"word\"", "int main() {}\n");
+        assert!(matches!(res, Err(SubmitError::Challenge)), "{:?}", res);
+    }
+
+    #[test]
+    fn ordinary_pages_with_cloudflare_scripts_are_not_challenges() {
+        let _dirs = testutil::isolated_dirs();
+        let script = "<script src=\"/cdn-cgi/challenge-platform/h/b/scripts/\
+            jsd/main.js\"></script><p>Just a moment... cf-chl</p>";
+        let inner = site(false, true);
+        let (base_url, _) = serve(move |req| {
+            let mut res = inner(req);
+            res.body += script;
+            res
+        });
+        submit(&base_url, "p@ss \"word\"", "int main() {}\n").unwrap();
+    }
+
+    #[test]
+    fn challenge_status_and_title() {
+        let title = "<html><head><title>Just a moment...</title></head></html>";
+        assert!(is_challenge(StatusCode::FORBIDDEN, true, ""));
+        assert!(is_challenge(StatusCode::SERVICE_UNAVAILABLE, false, title));
+        assert!(!is_challenge(StatusCode::OK, true, title));
+        assert!(!is_challenge(StatusCode::FORBIDDEN, false, "<p>denied</p>"));
+    }
+
+    #[test]
+    fn csrf_token_from_input_or_meta() {
+        assert_eq!(csrf_token(&csrf_html("abc", false)).unwrap(), "abc");
+        assert_eq!(csrf_token(&csrf_html("abc", true)).unwrap(), "abc");
+        assert_eq!(csrf_token("<html></html>"), None);
+    }
+}
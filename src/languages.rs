@@ -0,0 +1,249 @@
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, DirBuilder};
+use std::path::PathBuf;
+
+/// A language solutions can be submitted in (an option of the programTypeId
+/// select on submit pages).
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Language {
+    pub id: i64,
+    pub name: String,
+}
+
+fn cache_path() -> Option<PathBuf> {
+    ProjectDirs::from(crate::NAME_QUL, crate::NAME_ORG, crate::NAME_BIN)
+        .map(|proj_dirs| proj_dirs.cache_dir().join(crate::LANGUAGES_FILE_NAME))
+}
+
+/// Languages listed in [`crate::PROGRAM_TYPE_ID_HELP`], used when the list
+/// hasn't been fetched from Codeforces.
+pub fn builtin() -> Vec<Language> {
+    crate::PROGRAM_TYPE_ID_HELP
+        .lines()
+        .filter_map(|l| {
+            let mut parts = l.splitn(2, ' ');
+            let id = parts.next()?.parse().ok()?;
+            let name = parts.next()?.trim().to_string();
+            Some(Language { id, name })
+        })
+        .collect()
+}
+
+/// Languages stored by the last [`store`] (if any).
+pub fn cached() -> Option<Vec<Language>> {
+    let s = fs::read_to_string(cache_path()?).ok()?;
+    serde_yaml::from_str(&s).ok()
+}
+
+pub fn store(langs: &[Language]) -> Result<(), String> {
+    let path = match cache_path() {
+        Some(p) => p,
+        None => return Err("couldn't find a valid path for the cache".into()),
+    };
+    if let Some(parent) = path.parent() {
+        if let Err(e) = DirBuilder::new().recursive(true).create(parent) {
+            return Err(format!("could not create cache dir: {}", e));
+        }
+    }
+    // unwrap is probably ok here since serializing errors are very rare
+    let s = serde_yaml::to_string(langs).unwrap();
+    fs::write(&path, s)
+        .map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
+
+/// The cached list of languages, falling back to the builtin list.
+pub fn known() -> Vec<Language> {
+    cached().unwrap_or_else(builtin)
+}
+
+/// Names are compared ignoring case and whitespace (so `g++20` matches
+/// `GNU G++20 11.2.0 (64 bit, winlibs)`).
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Languages whose name contains `query`.
+pub fn search<'a>(langs: &'a [Language], query: &str) -> Vec<&'a Language> {
+    let query = normalize(query);
+    langs
+        .iter()
+        .filter(|l| normalize(&l.name).contains(&query))
+        .collect()
+}
+
+/// Version of a language (eg. `[1, 89, 0]` for `Rust 1.89.0 (2021)`): the
+/// first word of its name made of numbers separated by dots.
+fn version(name: &str) -> Option<Vec<u64>> {
+    name.split_whitespace()
+        .map(|w| w.trim_matches(|c| c == '(' || c == ')' || c == ','))
+        .find_map(|w| w.split('.').map(|n| n.parse().ok()).collect())
+}
+
+/// Resolve a programTypeId given either as a number or (part of) the name of
+/// a known language (eg. `rust` or `g++20`). A name which is matched exactly
+/// wins, otherwise the newest version (by [`version`]) out of the matching
+/// languages is chosen, and the first one listed out of those with the same
+/// version.
+pub fn resolve(s: &str) -> Result<i64, String> {
+    if let Ok(id) = s.trim().parse() {
+        return Ok(id);
+    }
+    let langs = known();
+    let l = resolve_name(&langs, s)?;
+    eprintln!("using {} {} for `{}`", l.id, l.name, s.trim());
+    Ok(l.id)
+}
+
+fn resolve_name<'a>(
+    langs: &'a [Language],
+    s: &str,
+) -> Result<&'a Language, String> {
+    let matches = search(langs, s);
+    if let Some(l) = matches.iter().find(|l| normalize(&l.name) == normalize(s))
+    {
+        return Ok(l);
+    }
+    // rev so that the first of the newest is chosen (max_by_key returns the
+    // last maximum)
+    matches
+        .into_iter()
+        .rev()
+        .max_by_key(|l| version(&l.name))
+        .ok_or_else(|| {
+            format!("unknown language `{}` (see `caffeine languages`)", s)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Languages as listed on the submit page.
+    const LIVE: &[(i64, &str)] = &[
+        (43, "GNU GCC C11 5.1.0"),
+        (80, "Clang++20 Diagnostics"),
+        (52, "Clang++17 Diagnostics"),
+        (50, "GNU G++14 6.4.0"),
+        (54, "GNU G++17 7.3.0"),
+        (89, "GNU G++20 13.2 (64 bit, winlibs)"),
+        (73, "GNU G++20 11.2.0 (64 bit, winlibs)"),
+        (91, "GNU G++23 14.2 (64 bit, msys2)"),
+        (31, "Python 3.8.10"),
+        (70, "PyPy 3.9.10 (7.3.9, 64bit)"),
+        (75, "Rust 1.75.0 (2021)"),
+        (99, "Rust 1.89.0 (2021)"),
+        (98, "Rust 1.89.0 (2024)"),
+        (36, "Java 8 32bit"),
+        (87, "Java 21 64bit"),
+    ];
+
+    fn live() -> Vec<Language> {
+        LIVE.iter()
+            .map(|&(id, name)| Language {
+                id,
+                name: name.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_builtin() {
+        let langs = builtin();
+        assert_eq!(langs.len(), crate::PROGRAM_TYPE_ID_HELP.lines().count());
+        for (id, name) in [
+            (43, "GNU GCC C11 5.1.0"),
+            (2, "Microsoft Visual C++ 2010"),
+            (61, "GNU G++17 9.2.0 (64 bit, msys 2)"),
+            (55, "Node.js 12.6.3"),
+        ] {
+            assert!(
+                langs.iter().any(|l| l.id == id && l.name == name),
+                "{} {}",
+                id,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn normalizes() {
+        for (s, expected) in [
+            ("GNU G++20 13.2", "gnug++2013.2"),
+            ("  g++ 20 ", "g++20"),
+            ("\tRust\n", "rust"),
+            ("", ""),
+        ] {
+            assert_eq!(normalize(s), expected, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn searches() {
+        let langs = live();
+        for (query, expected) in [
+            ("rust", &[75, 99, 98][..]),
+            ("RUST 1.89", &[99, 98]),
+            ("g++20", &[80, 89, 73]),
+            ("gnu g++ 20", &[89, 73]),
+            ("python", &[31]),
+            ("cobol", &[]),
+        ] {
+            let ids: Vec<i64> =
+                search(&langs, query).iter().map(|l| l.id).collect();
+            assert_eq!(ids, expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn versions() {
+        for (name, expected) in [
+            ("Rust 1.89.0 (2021)", Some(vec![1, 89, 0])),
+            ("GNU G++20 13.2 (64 bit, winlibs)", Some(vec![13, 2])),
+            ("PyPy 3.9.10 (7.3.9, 64bit)", Some(vec![3, 9, 10])),
+            ("PyPy 2.7 (7.3.0)", Some(vec![2, 7])),
+            ("Java 21 64bit", Some(vec![21])),
+            ("C# 8, .NET Core 3.1", Some(vec![8])),
+            ("Java 1.8.0_241", None),
+            ("Clang++20 Diagnostics", None),
+        ] {
+            assert_eq!(version(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn resolves_names() {
+        let langs = live();
+        for (query, expected) in [
+            // exact names
+            ("GNU G++20 11.2.0 (64 bit, winlibs)", Ok(73)),
+            ("gnu g++17 7.3.0", Ok(54)),
+            ("Clang++20 Diagnostics", Ok(80)),
+            // unique parts
+            ("python", Ok(31)),
+            ("pypy", Ok(70)),
+            ("winlibs", Ok(89)),
+            // ambiguous: the newest, else the first listed
+            ("rust", Ok(99)),
+            ("g++20", Ok(89)),
+            ("g++", Ok(91)),
+            ("java", Ok(87)),
+            ("diagnostics", Ok(80)),
+            // unknown
+            ("cobol", Err(())),
+            ("rust 2.0", Err(())),
+        ] {
+            let id = resolve_name(&langs, query).map(|l| l.id).map_err(|_| ());
+            assert_eq!(id, expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn resolves_ids() {
+        assert_eq!(resolve("54"), Ok(54));
+        assert_eq!(resolve(" 1000 "), Ok(1000));
+    }
+}
@@ -20,7 +20,8 @@
 //!
 //! ### Functionality:
 //! - Full access to the every API method provided by the Codeforces platform.
-//! - Download testcases for any given problem.
+//! - Download testcases (inputs and expected outputs) for any given problem.
+//! - Compile and test solutions locally against the sample testcases.
 //! - Submit solution to any given problem from either a file or `stdin`.
 //! - Stores default settings in a config file.
 //! - Stores login details in a file for easier usage.
@@ -33,26 +34,81 @@
 //! should try setting the `CHROME` environment variable before running. For
 //! Brave browser, for example, you might run `export CHROME=/usr/bin/brave`.
 //!
+//! Alternatively, solutions can be submitted with plain HTTP requests (no
+//! browser required) by using `--backend http` (or `submit_backend: http` in
+//! config.yml).
+//!
 //! Submitting solutions also requires that you provide your username and
 //! password. You can provide these with `caffeine login` or more explicitly
-//! with the `--handle`/`-H` and `--password`/`-p` flags.
+//! with the `--handle`/`-H` and `--password`/`-p` flags. In scripts (or CI),
+//! the `CAFFEINE_HANDLE`/`CAFFEINE_PASSWORD` and
+//! `CAFFEINE_API_KEY`/`CAFFEINE_API_SECRET` environment variables can be used
+//! instead, or the password can be piped to
+//! `caffeine login --handle <handle> --password-stdin`.
+//!
+//! By default `caffeine login` stores credentials in plaintext in `auth.yml`.
+//! Setting `secret_store: secret-service` in config.yml keeps the API secret
+//! and password in your keyring instead (through libsecret's `secret-tool`),
+//! or `secret_store: file` in a separate file only you can read. On machines
+//! without a keyring, `caffeine login --encrypt` encrypts `auth.yml` with a
+//! passphrase instead. Run `eval $(caffeine unlock)` to only enter the
+//! passphrase once per shell.
+//!
+//! To keep credentials in a password manager, set `credential_command` in
+//! config.yml (eg. `credential_command: pass show codeforces`). The command's
+//! first line is used as the password, and `handle: ...`, `api_key: ...` and
+//! `api_secret: ...` lines (or `name=value` lines, like git credential
+//! helpers) provide the rest.
+//!
+//! After logging in, the session cookies are stored next to `auth.yml` and
+//! reused by later submissions (logging in again only once the session has
+//! expired). Run `caffeine logout` to remove the stored session.
 //!
 
-use clap::{crate_version, App, Arg};
+use clap::{crate_version, App, Arg, ArgGroup};
+mod api;
 mod auth;
+mod cache;
+mod checker;
 mod config;
+mod credential;
+mod crypto;
 mod handlers;
+mod http_submit;
+mod languages;
+mod output;
+mod permissions;
+mod preprocess;
+mod query;
+mod runner;
+mod secrets;
+mod session;
 mod submit;
+mod submit_error;
+mod target;
+mod testcases;
+#[cfg(test)]
+mod testutil;
+mod verdict;
+mod workspace;
 
 pub const NAME_QUL: &str = "dev";
 pub const NAME_ORG: &str = "thud";
 pub const NAME_BIN: &str = "caffeine";
 
 pub const AUTH_FILE_NAME: &str = "auth.yml";
+pub const SESSION_FILE_NAME: &str = "session.yml";
+pub const SECRETS_FILE_NAME: &str = "secrets.yml";
 pub const AUTH_HELP_MSG: &str = "To generate an API key & secret, go to \
                                  https://codeforces.com/settings/api";
 
 pub const CONF_FILE_NAME: &str = "config.yml";
+pub const WORKSPACE_FILE_NAME: &str = ".caffeine.yml";
+pub const CACHE_API_DIR_NAME: &str = "api";
+pub const LANGUAGES_FILE_NAME: &str = "languages.yml";
+pub const RATE_LIMIT_FILE_NAME: &str = "last_request";
+/// Languages shown (and accepted by name) until the list has been fetched with
+/// `caffeine languages`.
 pub const PROGRAM_TYPE_ID_HELP: &str = "43 GNU GCC C11 5.1.0
 52 Clang++17 Diagnostics
 42 GNU G++11 5.1.0
@@ -96,6 +152,61 @@ fn main() {
                         .help("Boolean Flag, return raw JSON")
                         .short("r")
                         .long("raw")
+                        .conflicts_with("format")
+                        .display_order(1000)
+                        .global(true),
+                Arg::with_name("format")
+                        .help("Output format of API results")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(output::FORMAT_NAMES)
+                        .display_order(1000)
+                        .global(true),
+                Arg::with_name("fields")
+                        .help("List of fields to output (eg. \
+                        id,problem.index,verdict)")
+                        .long("fields")
+                        .takes_value(true)
+                        .multiple(true)
+                        .require_delimiter(true)
+                        .conflicts_with("raw")
+                        .display_order(1000)
+                        .global(true),
+                Arg::with_name("filter")
+                        .help("Only output results matching expression (eg. \
+                        'verdict!=OK'), may be given multiple times")
+                        .long("filter")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .validator(|s| s.parse::<query::Filter>().map(|_| ()))
+                        .conflicts_with("raw")
+                        .display_order(1000)
+                        .global(true),
+                Arg::with_name("nocache")
+                        .help("Boolean Flag, ignore cached API responses")
+                        .long("no-cache")
+                        .display_order(1000)
+                        .global(true),
+                Arg::with_name("offline")
+                        .help("Boolean Flag, only use cached API responses \
+                        (never access the network)")
+                        .long("offline")
+                        .conflicts_with("nocache")
+                        .display_order(1000)
+                        .global(true),
+                Arg::with_name("retries")
+                        .help("Number of times to retry API requests which \
+                        fail temporarily (default 3)")
+                        .long("retries")
+                        .takes_value(true)
+                        .display_order(1000)
+                        .global(true),
+                Arg::with_name("mininterval")
+                        .help("Minimum number of seconds between API requests \
+                        (default 2)")
+                        .long("min-interval")
+                        .takes_value(true)
                         .display_order(1000)
                         .global(true),
                 Arg::with_name("key")
@@ -149,7 +260,9 @@ fn main() {
                 .about("Returns list of hacks in the specified contests")
                 .args(&[
                     Arg::with_name("CONTESTID")
-                    .help("contestId of specified contest (eg. 1466)")
+                    .help("contestId of specified contest (eg. 1466), or the \
+                        url of the contest or one of its problems")
+                    .validator(|s| s.parse::<target::ProblemId>().map(|_| ()))
                     .index(1)
                     .required(true)
                 ])
@@ -169,7 +282,9 @@ fn main() {
                 .about("Returns rating changes after a contest")
                 .args(&[
                     Arg::with_name("CONTESTID")
-                    .help("contestId of specified contest (eg. 1466)")
+                    .help("contestId of specified contest (eg. 1466), or the \
+                        url of the contest or one of its problems")
+                    .validator(|s| s.parse::<target::ProblemId>().map(|_| ()))
                     .index(1)
                     .required(true)
                 ])
@@ -180,7 +295,9 @@ fn main() {
                     requested part of the standings")
                 .args(&[
                     Arg::with_name("CONTESTID")
-                    .help("contestId of specified contest (eg. 1466)")
+                    .help("contestId of specified contest (eg. 1466), or the \
+                        url of the contest or one of its problems")
+                    .validator(|s| s.parse::<target::ProblemId>().map(|_| ()))
                     .index(1)
                     .required(true),
                     Arg::with_name("from")
@@ -223,7 +340,9 @@ fn main() {
                 .about("Returns submissions for specified contest")
                 .args(&[
                     Arg::with_name("CONTESTID")
-                    .help("contestId of specified contest (eg. 1466)")
+                    .help("contestId of specified contest (eg. 1466), or the \
+                        url of the contest or one of its problems")
+                    .validator(|s| s.parse::<target::ProblemId>().map(|_| ()))
                     .index(1)
                     .required(true),
                     Arg::with_name("handle")
@@ -248,7 +367,9 @@ fn main() {
                     specified contest")
                 .args(&[
                     Arg::with_name("CONTESTID")
-                    .help("contestId of specified contest (eg. 1466)")
+                    .help("contestId of specified contest (eg. 1466), or the \
+                        url of the contest or one of its problems")
+                    .validator(|s| s.parse::<target::ProblemId>().map(|_| ()))
                     .index(1)
                     .required(true),
                     Arg::with_name("wait")
@@ -269,6 +390,44 @@ fn main() {
                     .required(true),
                 ])
             )
+            .subcommand(
+                App::new("setup")
+                .about("Creates a directory for every problem in specified \
+                    contest containing a solution file and its testcases")
+                .args(&[
+                    Arg::with_name("CONTESTID")
+                    .help("contestId of specified contest (eg. 1466), or the \
+                        url of the contest or one of its problems")
+                    .validator(|s| s.parse::<target::ProblemId>().map(|_| ()))
+                    .index(1)
+                    .required(true),
+                    Arg::with_name("DIR")
+                    .help("Directory to create the workspace in (default \
+                        value = CONTESTID)")
+                    .index(2),
+                    Arg::with_name("template")
+                    .help("Template file for solutions (overrides \
+                        workspace.template in config.yml)")
+                    .long("template")
+                    .takes_value(true),
+                    Arg::with_name("wait")
+                    .help("Flag or Number value indicating whether to wait for \
+                    contest to start. (default value = 10 seconds)")
+                    .long("wait")
+                    .short("w")
+                    .default_value("10")
+                    .takes_value(true)
+                    .required(true),
+                    Arg::with_name("timeout")
+                    .help("Program timeout value (default 1000 seconds). Only \
+                        used if wait enabled.")
+                    .long("timeout")
+                    .short("t")
+                    .takes_value(true)
+                    .default_value("1000")
+                    .required(true),
+                ])
+            )
         )
         .subcommand(
             App::new("problemset")
@@ -419,18 +578,19 @@ fn main() {
                 .short("p")
                 .takes_value(true),
                 Arg::with_name("CONTESTID")
-                .help("contestId of requested problem")
+                .help("contestId of requested problem, or the problem's code \
+                    (eg. 1466B) or url (in which case PROBLEMID is omitted). \
+                    If both are omitted, they are inferred from FILENAME")
                 .index(1)
                 .required(true)
                 .takes_value(true),
                 Arg::with_name("PROBLEMID")
                 .help("problemId of requested problem (eg A)")
                 .index(2)
-                .required(true)
                 .takes_value(true),
                 Arg::with_name("programtypeid")
-                .help("programTypeId of solution to be submitted (eg 54 = \
-                C++17)")
+                .help("programTypeId of solution to be submitted, or the name \
+                of its language (eg 54 or g++17, see `caffeine languages`)")
                 .long("programtypeid")
                 .short("l")
                 .takes_value(true),
@@ -440,11 +600,115 @@ fn main() {
                 .long("mirror")
                 .short("m")
                 .takes_value(true),
+                Arg::with_name("baseurl")
+                .help("Base URL of Codeforces to submit to (eg. \
+                    http://localhost:8080/), overrides --mirror")
+                .long("base-url")
+                .conflicts_with("mirror")
+                .takes_value(true),
+                Arg::with_name("backend")
+                .help("Method used to submit (chrome = headless browser, http \
+                    = plain HTTP requests)")
+                .long("backend")
+                .short("b")
+                .possible_values(submit::BACKEND_NAMES)
+                .takes_value(true),
+                Arg::with_name("gym")
+                .help("Boolean Flag, CONTESTID is a gym contest")
+                .long("gym")
+                .conflicts_with_all(&["group", "problemset"]),
+                Arg::with_name("group")
+                .help("Code of the group which the contest belongs to (eg. \
+                    MWSDmqGsZm)")
+                .long("group")
+                .conflicts_with("problemset")
+                .takes_value(true),
+                Arg::with_name("problemset")
+                .help("Boolean Flag, submit to the problemset rather than to \
+                    the contest")
+                .long("problemset"),
                 Arg::with_name("FILENAME")
                 .help("String value, filename of solution to be submitted \
-                    (alternatively use piped stdin)")
+                    (alternatively use piped stdin). Several solutions can \
+                    be submitted at once as PROBLEM=FILE pairs (eg. \
+                    `caffeine submit 1466 A=a.cpp B=b.cpp`) or by giving a \
+                    workspace directory")
                 .index(3)
+                .multiple(true)
+                .takes_value(true),
+                Arg::with_name("wait")
+                .help("Boolean Flag, wait for the verdict after submitting \
+                    (exit code depends on the verdict)")
+                .long("wait")
+                .short("w"),
+                Arg::with_name("judgingtimeout")
+                .help("Number of seconds to wait for the verdict before \
+                    giving up (default 600, overrides judging_timeout in \
+                    config.yml)")
+                .long("judging-timeout")
+                .requires("wait")
+                .takes_value(true),
+                Arg::with_name("testfirst")
+                .help("Boolean Flag, run the solution against its sample \
+                    testcases first and only submit if they pass")
+                .long("test-first")
+                .conflicts_with("notestfirst"),
+                Arg::with_name("nopreprocess")
+                .help("Boolean Flag, submit the source as is (ignoring \
+                    preprocess in config.yml)")
+                .long("no-preprocess"),
+                Arg::with_name("notestfirst")
+                .help("Boolean Flag, don't test before submitting (overrides \
+                    test_first in config.yml)")
+                .long("no-test-first"),
+                Arg::with_name("dir")
+                .help("Directory containing the testcases (default value = \
+                    directory of FILENAME)")
+                .long("dir")
+                .short("d")
                 .takes_value(true),
+                Arg::with_name("checker")
+                .help("Checker used to compare outputs when testing first \
+                    (eg. tokens, yesno, float:1e-6 or custom:./checker)")
+                .long("checker")
+                .short("c")
+                .takes_value(true)
+                .validator(|s| s.parse::<checker::Checker>().map(|_| ())),
+            ])
+        )
+        .subcommand(
+            App::new("test")
+            .about("Compile and run a solution against its sample testcases")
+            .args(&[
+                Arg::with_name("FILENAME")
+                .help("String value, filename of solution to be tested")
+                .index(1)
+                .required(true)
+                .takes_value(true),
+                Arg::with_name("programtypeid")
+                .help("programTypeId of solution to be tested, or the name of \
+                its language (eg 54 or g++17, see `caffeine languages`)")
+                .long("programtypeid")
+                .short("l")
+                .takes_value(true),
+                Arg::with_name("timelimit")
+                .help("Time limit in seconds for each testcase (default 2)")
+                .long("time-limit")
+                .short("T")
+                .takes_value(true),
+                Arg::with_name("dir")
+                .help("Directory containing the testcases (default value = \
+                    directory of FILENAME)")
+                .long("dir")
+                .short("d")
+                .takes_value(true),
+                Arg::with_name("checker")
+                .help("Checker used to compare outputs (eg. tokens, yesno, \
+                    float:1e-6 or custom:./checker)")
+                .long("checker")
+                .short("c")
+                .takes_value(true)
+                .validator(|s| s.parse::<checker::Checker>().map(|_| ())),
             ])
         )
         .subcommand(
@@ -457,7 +721,7 @@ fn main() {
                     argument (not recommended)")
                 .long("handle")
                 .short("H")
-                .requires("password")
+                .requires("login-password")
                 .takes_value(true),
                 Arg::with_name("password")
                 .help("String value, provide login password as cli argument \
@@ -466,7 +730,84 @@ fn main() {
                 .short("p")
                 .requires("handle")
                 .takes_value(true),
+                Arg::with_name("password-stdin")
+                .help("Boolean Flag, read the login password from stdin (eg. \
+                    from a password manager in scripts)")
+                .long("password-stdin")
+                .requires("handle"),
+                Arg::with_name("encrypt")
+                .help("Boolean Flag, encrypt auth.yml with a passphrase \
+                    (prompted for whenever credentials are needed, see \
+                    `caffeine unlock`)")
+                .long("encrypt"),
             ])
+            .group(
+                ArgGroup::with_name("login-password")
+                .args(&["password", "password-stdin"])
+            )
+        )
+        .subcommand(
+            App::new("languages")
+            .about("List the languages (programTypeIds) solutions can be \
+                submitted in")
+            .args(&[
+                Arg::with_name("QUERY")
+                .help("Only list languages whose name contains QUERY (eg. \
+                    rust or g++)")
+                .index(1),
+                Arg::with_name("refresh")
+                .help("Boolean Flag, fetch the list from Codeforces again \
+                    rather than using the cached list")
+                .long("refresh"),
+                Arg::with_name("handle")
+                .help("Handle or email to login with when fetching the list \
+                    (not recommended)")
+                .long("handle")
+                .short("H")
+                .requires("password")
+                .takes_value(true),
+                Arg::with_name("password")
+                .help("Password to login with when fetching the list (not \
+                    recommended)")
+                .long("password")
+                .short("p")
+                .requires("handle")
+                .takes_value(true),
+            ])
+        )
+        .subcommand(
+            App::new("logout")
+            .about("Remove the stored login session (login details are \
+            kept)")
+        )
+        .subcommand(
+            App::new("doctor")
+            .about("Check that files holding credentials can only be accessed \
+            by you")
+            .args(&[
+                Arg::with_name("strict")
+                .help("Boolean Flag, exit with an error if any problems are \
+                    found")
+                .long("strict"),
+            ])
+        )
+        .subcommand(
+            App::new("unlock")
+            .about("Print the key unlocking an encrypted auth.yml as a shell \
+            command (eg. `eval $(caffeine unlock)`), so that the passphrase \
+            isn't asked for again in that shell")
+        )
+        .subcommand(
+            App::new("cache")
+            .about("Manage locally cached API responses")
+            .subcommand(
+                App::new("clear")
+                .about("Remove all cached API responses")
+            )
+            .subcommand(
+                App::new("stats")
+                .about("Show the location, size and contents of the cache")
+            )
         )
         .subcommand(
             App::new("config")
@@ -479,8 +820,8 @@ fn main() {
                 .short("u")
                 .takes_value(true),
                 Arg::with_name("defaultprogramtypeid")
-                .help("Number, set default programTypeId language for \
-                    submitting code (eg. 54 = C++17) as cli argument")
+                .help("Set default programTypeId (or language name) for \
+                    submitting code (eg. 54 or g++17) as cli argument")
                 .long("lang")
                 .short("l")
                 .takes_value(true),
@@ -508,12 +849,30 @@ fn main() {
         ("submit", Some(args)) => {
             handlers::submit_command(args);
         }
+        ("test", Some(args)) => {
+            handlers::test_command(args);
+        }
         ("login", Some(args)) => {
             handlers::login_command(args);
         }
+        ("languages", Some(args)) => {
+            handlers::languages_command(args);
+        }
+        ("logout", Some(_)) => {
+            handlers::logout_command();
+        }
+        ("doctor", Some(args)) => {
+            handlers::doctor_command(args);
+        }
+        ("unlock", Some(_)) => {
+            handlers::unlock_command();
+        }
         ("config", Some(args)) => {
             handlers::config_command(args);
         }
+        ("cache", Some(subcommand)) => {
+            handlers::cache_command(subcommand);
+        }
         _ => {
             handlers::exit_with_usage(&matches);
         }
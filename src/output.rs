@@ -0,0 +1,481 @@
+use clap::ArgMatches;
+use codeforces_api::responses::CFResult;
+use serde_json::Value;
+use std::str::FromStr;
+
+use crate::query::Query;
+
+pub const FORMAT_NAMES: &[&str] = &["yaml", "json", "table", "tsv", "csv"];
+
+/// Output format selected with the global `--format` option.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Yaml,
+    Json,
+    Table,
+    Tsv,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "json" => Ok(Format::Json),
+            "table" => Ok(Format::Table),
+            "tsv" => Ok(Format::Tsv),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!(
+                "unknown format `{}` (expected one of: {})",
+                s,
+                FORMAT_NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+pub fn get_format(args: &ArgMatches) -> Format {
+    crate::handlers::get_optional_arg_of_type(args, "format")
+        .unwrap_or(Format::Yaml)
+}
+
+/// Print a decoded API result in the format requested on the command line.
+pub fn print_result(args: &ArgMatches, res: &CFResult) {
+    match get_format(args) {
+        // Keep the exact output produced by `CFResult`'s Display impl.
+        Format::Yaml if Query::from_args(args).is_empty() => {
+            println!("{}", res)
+        }
+        _ => {
+            // unwrap is probably ok here since serializing errors are very rare
+            let v = serde_json::to_value(res).unwrap();
+            print_value(args, &v, Some(default_columns(res)));
+        }
+    }
+}
+
+/// Print an arbitrary JSON value in the format requested on the command line.
+///
+/// `columns` is the list of (dot separated) paths shown by the `table` format
+/// when no `--fields` are given. If `None`, every leaf value is shown as its
+/// own column.
+///
+/// If `--fields` or `--filter` are given, then only the matching records (see
+/// [`records`]) are printed rather than the whole value.
+pub fn print_value(args: &ArgMatches, v: &Value, columns: Option<&[&str]>) {
+    let query = Query::from_args(args);
+    let queried;
+    let v = if query.is_empty() {
+        v
+    } else {
+        queried = Value::Array(query.apply(records(v)));
+        &queried
+    };
+    let has_fields = query.fields.is_some();
+    let columns: Option<Vec<String>> = match query.fields {
+        Some(f) => Some(f),
+        None => columns.map(|c| c.iter().map(|s| s.to_string()).collect()),
+    };
+
+    match get_format(args) {
+        Format::Yaml => {
+            let s = serde_yaml::to_string(v).unwrap();
+            println!("{}", s);
+        }
+        Format::Json => {
+            println!("{}", serde_json::to_string_pretty(v).unwrap());
+        }
+        Format::Table => {
+            let rows = records(v);
+            let cols = columns.unwrap_or_else(|| all_columns(&rows));
+            print!("{}", format_table(&cols, &rows));
+        }
+        Format::Tsv | Format::Csv => {
+            let rows = records(v);
+            // Scripts get every field unless they ask for specific ones.
+            let cols = match columns {
+                Some(c) if has_fields => c,
+                _ => all_columns(&rows),
+            };
+            let delim = if get_format(args) == Format::Tsv {
+                '\t'
+            } else {
+                ','
+            };
+            print!("{}", format_delimited(&cols, &rows, delim));
+        }
+    }
+}
+
+/// Default set of columns shown by the `table` format for each kind of result.
+pub fn default_columns(res: &CFResult) -> &'static [&'static str] {
+    match res {
+        CFResult::CFCommentVec(_) => {
+            &["id", "commentatorHandle", "rating", "creationTimeSeconds"]
+        }
+        CFResult::CFBlogEntry(_) | CFResult::CFBlogEntryVec(_) => &[
+            "id",
+            "authorHandle",
+            "title",
+            "rating",
+            "creationTimeSeconds",
+        ],
+        CFResult::CFHackVec(_) => &[
+            "id",
+            "problem.index",
+            "hacker.members.0.handle",
+            "defender.members.0.handle",
+            "verdict",
+        ],
+        CFResult::CFContestVec(_) => &[
+            "id",
+            "name",
+            "type",
+            "phase",
+            "startTimeSeconds",
+            "durationSeconds",
+        ],
+        CFResult::CFRatingChangeVec(_) => {
+            &["contestId", "handle", "rank", "oldRating", "newRating"]
+        }
+        CFResult::CFContestStandings(_) => &[
+            "rank",
+            "party.members.0.handle",
+            "points",
+            "penalty",
+            "successfulHackCount",
+            "unsuccessfulHackCount",
+        ],
+        CFResult::CFSubmissionVec(_) => &[
+            "id",
+            "contestId",
+            "problem.index",
+            "problem.name",
+            "programmingLanguage",
+            "verdict",
+            "passedTestCount",
+            "timeConsumedMillis",
+            "memoryConsumedBytes",
+        ],
+        CFResult::CFProblemset(_) => {
+            &["contestId", "index", "name", "rating", "tags"]
+        }
+        CFResult::CFRecentActionVec(_) => &[
+            "timeSeconds",
+            "blogEntry.id",
+            "blogEntry.title",
+            "comment.commentatorHandle",
+        ],
+        CFResult::CFFriends(_) => &["value"],
+        CFResult::CFUserVec(_) => &[
+            "handle",
+            "rating",
+            "maxRating",
+            "rank",
+            "country",
+            "organization",
+        ],
+    }
+}
+
+/// Split a value into the list of records shown by the tabular formats.
+///
+/// Arrays are split into their elements, whilst standings and problemsets are
+/// split into their ranklist rows and problems respectively.
+pub fn records(v: &Value) -> Vec<Value> {
+    match v {
+        Value::Array(a) => a.clone(),
+        Value::Object(m) => {
+            if let (Some(Value::Array(a)), true) =
+                (m.get("rows"), m.contains_key("contest"))
+            {
+                a.clone()
+            } else if let (Some(Value::Array(a)), true) =
+                (m.get("problems"), m.contains_key("problemStatistics"))
+            {
+                a.clone()
+            } else {
+                vec![v.clone()]
+            }
+        }
+        _ => vec![v.clone()],
+    }
+}
+
+/// Look up a dot separated path (eg. `problem.index` or
+/// `party.members.0.handle`) in a value.
+pub fn lookup<'a>(v: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() || (path == "value" && !v.is_object()) {
+        return Some(v);
+    }
+    path.split('.').try_fold(v, |cur, key| match cur {
+        Value::Object(m) => m.get(key),
+        Value::Array(a) => key.parse::<usize>().ok().and_then(|i| a.get(i)),
+        _ => None,
+    })
+}
+
+/// Convert a value into the text shown in a single cell. Lists of scalars are
+/// joined with `;` (as the Codeforces API does for its list parameters).
+pub fn cell(v: Option<&Value>) -> String {
+    match v {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.to_string(),
+        Some(Value::Array(a)) if a.iter().all(|x| !x.is_object()) => a
+            .iter()
+            .map(|x| cell(Some(x)))
+            .collect::<Vec<String>>()
+            .join(";"),
+        Some(x) => x.to_string(),
+    }
+}
+
+/// Collect every leaf path of the given records, in order of first appearance.
+fn all_columns(rows: &[Value]) -> Vec<String> {
+    let mut cols = vec![];
+    for r in rows {
+        collect_paths(r, "", &mut cols);
+    }
+    cols
+}
+
+fn collect_paths(v: &Value, prefix: &str, cols: &mut Vec<String>) {
+    let join = |k: &str| {
+        if prefix.is_empty() {
+            k.to_string()
+        } else {
+            format!("{}.{}", prefix, k)
+        }
+    };
+    match v {
+        Value::Object(m) => {
+            for (k, x) in m {
+                collect_paths(x, &join(k), cols);
+            }
+        }
+        Value::Array(a) if a.iter().any(|x| x.is_object()) => {
+            for (i, x) in a.iter().enumerate() {
+                collect_paths(x, &join(&i.to_string()), cols);
+            }
+        }
+        _ => {
+            let p = if prefix.is_empty() {
+                "value".to_string()
+            } else {
+                prefix.to_string()
+            };
+            if !cols.contains(&p) {
+                cols.push(p);
+            }
+        }
+    }
+}
+
+/// Lay out records as aligned columns, one line per record after a header.
+fn format_table(cols: &[String], rows: &[Value]) -> String {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|r| {
+            cols.iter()
+                .map(|c| cell(lookup(r, c)).replace('\n', " "))
+                .collect()
+        })
+        .collect();
+    let mut widths: Vec<usize> =
+        cols.iter().map(|c| c.chars().count()).collect();
+    for row in &cells {
+        for (w, c) in widths.iter_mut().zip(row) {
+            *w = std::cmp::max(*w, c.chars().count());
+        }
+    }
+    let mut out = String::new();
+    let mut push_row = |row: &[String]| {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{:<width$}", c, width = w))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    };
+    push_row(cols);
+    for row in &cells {
+        push_row(row);
+    }
+    out
+}
+
+/// Lay out records as CSV (`,`) or TSV (`\t`) lines after a header.
+fn format_delimited(cols: &[String], rows: &[Value], delim: char) -> String {
+    let escape = |s: String| -> String {
+        if delim == '\t' {
+            s.replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+        } else if s.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s
+        }
+    };
+    let sep = delim.to_string();
+    let header: Vec<String> =
+        cols.iter().map(|c| escape(c.to_string())).collect();
+    let mut out = header.join(&sep) + "\n";
+    for r in rows {
+        let line: Vec<String> =
+            cols.iter().map(|c| escape(cell(lookup(r, c)))).collect();
+        out.push_str(&line.join(&sep));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cols(c: &[&str]) -> Vec<String> {
+        c.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn renders_cells() {
+        for (v, expected) in [
+            (json!(null), ""),
+            (json!("A"), "A"),
+            (json!(1466), "1466"),
+            (json!(4.5), "4.5"),
+            (json!(true), "true"),
+            (json!([]), ""),
+            (json!(["dp", "greedy"]), "dp;greedy"),
+            (json!([1, null, "x"]), "1;;x"),
+            (json!([["a", "b"], "c"]), "a;b;c"),
+            (json!({"handle": "tourist"}), "{\"handle\":\"tourist\"}"),
+            (json!([{"handle": "tourist"}]), "[{\"handle\":\"tourist\"}]"),
+        ] {
+            assert_eq!(cell(Some(&v)), expected, "{}", v);
+        }
+        assert_eq!(cell(None), "");
+    }
+
+    #[test]
+    fn looks_up_paths() {
+        let v = json!({
+            "id": 1,
+            "problem": {"index": "A", "tags": ["dp", "math"]},
+            "party": {"members": [{"handle": "tourist"}, {"handle": "thud"}]},
+            "value": "x",
+        });
+        for (path, expected) in [
+            ("id", Some(json!(1))),
+            ("problem.index", Some(json!("A"))),
+            ("problem.tags.1", Some(json!("math"))),
+            ("party.members.1.handle", Some(json!("thud"))),
+            ("value", Some(json!("x"))),
+            ("", Some(v.clone())),
+            ("problem.name", None),
+            ("problem.tags.2", None),
+            ("party.members.first", None),
+            ("id.x", None),
+        ] {
+            assert_eq!(lookup(&v, path), expected.as_ref(), "{}", path);
+        }
+        // `value` is the whole of a scalar (eg. each of `user.friends`)
+        assert_eq!(lookup(&json!("thud"), "value"), Some(&json!("thud")));
+        assert_eq!(lookup(&json!(["a"]), "0"), Some(&json!("a")));
+    }
+
+    #[test]
+    fn splits_records() {
+        let standings = json!({
+            "contest": {"id": 1},
+            "problems": [],
+            "rows": [{"rank": 1}, {"rank": 2}],
+        });
+        let problemset = json!({
+            "problems": [{"index": "A"}],
+            "problemStatistics": [],
+        });
+        for (v, expected) in [
+            (json!([1, 2]), vec![json!(1), json!(2)]),
+            (json!([]), vec![]),
+            (standings, vec![json!({"rank": 1}), json!({"rank": 2})]),
+            (problemset, vec![json!({"index": "A"})]),
+            // other objects (eg. `blogEntry.view`) are a single record
+            (json!({"rows": [1]}), vec![json!({"rows": [1]})]),
+            (json!({"id": 1}), vec![json!({"id": 1})]),
+            (json!("thud"), vec![json!("thud")]),
+            (json!(null), vec![json!(null)]),
+        ] {
+            assert_eq!(records(&v), expected, "{}", v);
+        }
+    }
+
+    #[test]
+    fn collects_columns() {
+        let rows = [
+            json!({"id": 1, "author": {"members": [{"handle": "a"}]}}),
+            json!({"id": 2, "tags": ["dp"], "points": null}),
+        ];
+        assert_eq!(
+            all_columns(&rows),
+            cols(&["id", "author.members.0.handle", "tags", "points"])
+        );
+        assert_eq!(all_columns(&[json!("thud")]), cols(&["value"]));
+    }
+
+    #[test]
+    fn quotes_delimited_values() {
+        let rows = [json!({
+            "plain": "x",
+            "comma": "a,b",
+            "quote": "say \"hi\"",
+            "newline": "a\nb",
+            "tab": "a\tb",
+            "slash": "a\\b",
+            "tags": ["dp", "math"],
+        })];
+        let c = cols(&[
+            "plain", "comma", "quote", "newline", "tab", "slash", "tags", "x,y",
+        ]);
+        for (delim, expected) in [
+            (
+                ',',
+                "plain,comma,quote,newline,tab,slash,tags,\"x,y\"\n\
+                x,\"a,b\",\"say \"\"hi\"\"\",\"a\nb\",a\tb,a\\b,dp;math,\n",
+            ),
+            (
+                '\t',
+                "plain\tcomma\tquote\tnewline\ttab\tslash\ttags\tx,y\n\
+                x\ta,b\tsay \"hi\"\ta\\nb\ta\\tb\ta\\\\b\tdp;math\t\n",
+            ),
+        ] {
+            assert_eq!(
+                format_delimited(&c, &rows, delim),
+                expected,
+                "{:?}",
+                delim
+            );
+        }
+    }
+
+    #[test]
+    fn aligns_tables() {
+        let rows = [
+            json!({"id": 1466, "name": "Codeforces Round", "tags": ["a"]}),
+            json!({"id": 7, "name": "Two\nlines"}),
+        ];
+        assert_eq!(
+            format_table(&cols(&["id", "name", "tags"]), &rows),
+            "id    name              tags\n\
+            1466  Codeforces Round  a\n\
+            7     Two lines\n"
+        );
+        assert_eq!(format_table(&cols(&["id"]), &[]), "id\n");
+    }
+}
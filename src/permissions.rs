@@ -0,0 +1,208 @@
+use directories::ProjectDirs;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use std::os::unix::fs::{
+    DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt,
+};
+
+/// Modes of the data dir and of the files holding credentials in it, which
+/// only the user should be able to access.
+#[cfg(unix)]
+const PRIVATE_DIR_MODE: u32 = 0o700;
+#[cfg(unix)]
+const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Files in the data dir which hold credentials (login details, API keys and
+/// session cookies).
+const PRIVATE_FILES: &[&str] = &[
+    crate::AUTH_FILE_NAME,
+    crate::SESSION_FILE_NAME,
+    crate::SECRETS_FILE_NAME,
+];
+
+/// Create a directory (and its parents) which only the user can access. The
+/// mode only applies to the directory itself, not to parents created along
+/// the way. The mode of an existing directory is restricted too.
+pub fn create_private_dir(dir: &Path) -> IoResult<()> {
+    if let Some(parent) = dir.parent() {
+        DirBuilder::new().recursive(true).create(parent)?;
+    }
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    builder.mode(PRIVATE_DIR_MODE);
+    match builder.create(dir) {
+        Err(e) if e.kind() == ErrorKind::AlreadyExists && dir.is_dir() => {
+            #[cfg(unix)]
+            if fs::metadata(dir)?.mode() & 0o077 != 0 {
+                fs::set_permissions(
+                    dir,
+                    fs::Permissions::from_mode(PRIVATE_DIR_MODE),
+                )?;
+            }
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+/// Open a file which only the user can access for writing, creating it (and
+/// its directory) if needed. The mode of an existing file is restricted too.
+/// Symlinks are refused, so that credentials can't be written (and the mode
+/// changed) elsewhere.
+pub fn open_private_file(path: &Path, truncate: bool) -> IoResult<fs::File> {
+    if let Some(dir) = path.parent() {
+        create_private_dir(dir)?;
+    }
+    let refuse_symlink = || {
+        IoError::other(format!(
+            "{} is a symlink, refusing to write to it",
+            path.display()
+        ))
+    };
+    if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink()) {
+        return Err(refuse_symlink());
+    }
+    let mut options = OpenOptions::new();
+    options
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(truncate);
+    #[cfg(unix)]
+    options
+        .mode(PRIVATE_FILE_MODE)
+        .custom_flags(libc::O_NOFOLLOW);
+    let f = options.open(path).map_err(|e| {
+        // the symlink may have been created since checking
+        #[cfg(unix)]
+        if e.raw_os_error() == Some(libc::ELOOP) {
+            return refuse_symlink();
+        }
+        e
+    })?;
+    #[cfg(unix)]
+    f.set_permissions(fs::Permissions::from_mode(PRIVATE_FILE_MODE))?;
+    Ok(f)
+}
+
+/// Replace the contents of a file which only the user can access.
+pub fn write_private_file(path: &Path, contents: &str) -> IoResult<()> {
+    open_private_file(path, true)?.write_all(contents.as_bytes())
+}
+
+#[cfg(unix)]
+fn current_uid() -> u32 {
+    // safe since getuid can't fail
+    unsafe { libc::getuid() }
+}
+
+/// Problems with who can access `path` (which should only be accessible by
+/// the user), or an empty list if there are none (or `path` doesn't exist).
+pub fn check_private(path: &Path, is_dir: bool) -> Vec<String> {
+    let mut problems = Vec::new();
+    let meta = match fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(_) => return problems,
+    };
+    if meta.file_type().is_symlink() {
+        problems.push("is a symlink".to_string());
+    }
+    #[cfg(unix)]
+    {
+        // the permissions of the file a symlink points to are what matter
+        let meta = fs::metadata(path).unwrap_or(meta);
+        if meta.uid() != current_uid() {
+            problems
+                .push(format!("is owned by another user (uid {})", meta.uid()));
+        }
+        let mode = meta.mode() & 0o777;
+        if mode & 0o077 != 0 {
+            let expected = if is_dir {
+                PRIVATE_DIR_MODE
+            } else {
+                PRIVATE_FILE_MODE
+            };
+            problems.push(format!(
+                "is accessible by group/other (mode {:o}, should be {:o})",
+                mode, expected
+            ));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = is_dir;
+    problems
+}
+
+/// Check the data dir and every file holding credentials, returning each
+/// path along with its problems (paths without problems are included with an
+/// empty list, paths which don't exist are skipped).
+pub fn audit() -> Result<Vec<(PathBuf, Vec<String>)>, String> {
+    let proj_dirs =
+        ProjectDirs::from(crate::NAME_QUL, crate::NAME_ORG, crate::NAME_BIN)
+            .ok_or("couldn't find a valid path to store keys")?;
+    let dir = proj_dirs.data_dir();
+    let mut res = Vec::new();
+    if fs::symlink_metadata(dir).is_ok() {
+        res.push((dir.to_path_buf(), check_private(dir, true)));
+    }
+    for name in PRIVATE_FILES {
+        let path = dir.join(name);
+        if fs::symlink_metadata(&path).is_ok() {
+            let problems = check_private(&path, false);
+            res.push((path, problems));
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use std::os::unix::fs::symlink;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().mode() & 0o777
+    }
+
+    #[test]
+    fn restricts_existing_dir() {
+        let _dirs = testutil::isolated_dirs();
+        let dir = testutil::proj_dirs().data_dir().to_path_buf();
+        fs::create_dir_all(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        create_private_dir(&dir).unwrap();
+        assert_eq!(mode(&dir), PRIVATE_DIR_MODE);
+
+        let path = dir.join(crate::AUTH_FILE_NAME);
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private_file(&path, "x").unwrap();
+        assert_eq!(mode(&dir), PRIVATE_DIR_MODE);
+        assert_eq!(mode(&path), PRIVATE_FILE_MODE);
+        assert!(audit().unwrap().iter().all(|(_, p)| p.is_empty()));
+    }
+
+    #[test]
+    fn refuses_symlinks() {
+        let _dirs = testutil::isolated_dirs();
+        let dir = testutil::proj_dirs().data_dir().to_path_buf();
+        create_private_dir(&dir).unwrap();
+        let target = dir.with_file_name("elsewhere.yml");
+        fs::write(&target, "unchanged").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o644))
+            .unwrap();
+        let path = dir.join(crate::AUTH_FILE_NAME);
+        symlink(&target, &path).unwrap();
+
+        let e = write_private_file(&path, "secret").unwrap_err();
+        assert!(e.to_string().contains("is a symlink"), "{}", e);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "unchanged");
+        assert_eq!(mode(&target), 0o644);
+        assert!(check_private(&path, false).contains(&"is a symlink".into()));
+    }
+}
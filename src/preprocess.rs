@@ -0,0 +1,490 @@
+use rand::Rng;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::workspace::expand_home;
+
+const DEFAULT_DEBUG_BEGIN: &str = "DEBUG-BEGIN";
+const DEFAULT_DEBUG_END: &str = "DEBUG-END";
+
+/// Syntax of a solution, which decides how libraries are inlined and how
+/// comments are written.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    /// C/C++, `#include "..."` headers are inlined.
+    C,
+    /// Rust, `mod name;` files are inlined.
+    Rust,
+    /// Other languages with `//` comments.
+    Slash,
+    /// Languages with `#` comments (eg. Python).
+    Hash,
+    /// Languages with `--` comments (eg. Haskell).
+    Dash,
+    /// OCaml (`(* ... *)` comments).
+    OCaml,
+}
+
+impl Syntax {
+    /// Syntax taken from the extension of the solution, else from the name of
+    /// its language (for solutions read from stdin).
+    fn detect(file: Option<&Path>, language: Option<&str>) -> Syntax {
+        let ext = file
+            .and_then(|f| f.extension())
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase);
+        if let Some(ext) = ext {
+            return match ext.as_str() {
+                "c" | "cc" | "cpp" | "cxx" | "c++" | "h" | "hh" | "hpp"
+                | "hxx" => Syntax::C,
+                "rs" => Syntax::Rust,
+                "py" | "rb" | "pl" | "r" | "jl" | "cr" | "sh" => Syntax::Hash,
+                "hs" | "lua" => Syntax::Dash,
+                "ml" => Syntax::OCaml,
+                _ => Syntax::Slash,
+            };
+        }
+        let name = language.unwrap_or_default().to_lowercase();
+        let has = |words: &[&str]| words.iter().any(|w| name.contains(w));
+        if has(&["rust"]) {
+            Syntax::Rust
+        } else if has(&["c++", "g++", "gcc", "clang", "c11"]) {
+            Syntax::C
+        } else if has(&["python", "pypy", "ruby", "perl"]) {
+            Syntax::Hash
+        } else if has(&["haskell"]) {
+            Syntax::Dash
+        } else if has(&["ocaml"]) {
+            Syntax::OCaml
+        } else {
+            Syntax::Slash
+        }
+    }
+
+    fn comment(self, text: &str) -> String {
+        match self {
+            Syntax::C | Syntax::Rust | Syntax::Slash => format!("// {}", text),
+            Syntax::Hash => format!("# {}", text),
+            Syntax::Dash => format!("-- {}", text),
+            Syntax::OCaml => format!("(* {} *)", text),
+        }
+    }
+}
+
+/// Transforms applied to solutions before they are submitted, configured by
+/// the `preprocess` map in config.yml.
+pub struct Preprocessor {
+    inline: bool,
+    include_dirs: Vec<PathBuf>,
+    /// Markers of the debug blocks to remove (if enabled).
+    debug_markers: Option<(String, String)>,
+    random_comment: bool,
+}
+
+impl Preprocessor {
+    pub fn from_config(conf: &Config) -> Preprocessor {
+        let p = conf.preprocess.clone().unwrap_or_default();
+        let debug_markers = if p.strip_debug.unwrap_or(false) {
+            Some((
+                p.debug_begin
+                    .unwrap_or_else(|| DEFAULT_DEBUG_BEGIN.to_string()),
+                p.debug_end.unwrap_or_else(|| DEFAULT_DEBUG_END.to_string()),
+            ))
+        } else {
+            None
+        };
+        Preprocessor {
+            inline: p.inline.unwrap_or(false),
+            include_dirs: p
+                .include_dirs
+                .unwrap_or_default()
+                .iter()
+                .map(|d| expand_home(d))
+                .collect(),
+            debug_markers,
+            random_comment: p.random_comment.unwrap_or(false),
+        }
+    }
+
+    /// Apply every enabled transform to the source of a solution. `file` is
+    /// the path of the solution (if it wasn't read from stdin), and
+    /// `language` the name of the language it is submitted in.
+    pub fn apply(
+        &self,
+        src: &str,
+        file: Option<&Path>,
+        language: Option<&str>,
+    ) -> Result<String, String> {
+        let syntax = Syntax::detect(file, language);
+        let mut inliner = Inliner {
+            pp: self,
+            syntax,
+            seen: HashSet::new(),
+        };
+        if let Some(f) = file.and_then(|f| fs::canonicalize(f).ok()) {
+            inliner.seen.insert(f);
+        }
+        let mut res = inliner.process(src, file, true)?;
+        if self.random_comment {
+            let id = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+            if !res.is_empty() && !res.ends_with('\n') {
+                res.push('\n');
+            }
+            res += &syntax.comment(&id);
+            res.push('\n');
+        }
+        Ok(res)
+    }
+
+    /// Remove every line between (and including) lines containing the begin
+    /// and end markers.
+    fn strip_debug(&self, src: &str, file: &str) -> Result<String, String> {
+        let (begin, end) = match self.debug_markers {
+            Some(ref m) => m,
+            None => return Ok(src.to_string()),
+        };
+        let mut res = String::new();
+        let mut skipping = false;
+        for line in src.split_inclusive('\n') {
+            if skipping {
+                skipping = !line.contains(end.as_str());
+            } else if line.contains(begin.as_str()) {
+                skipping = true;
+            } else {
+                res += line;
+            }
+        }
+        if skipping {
+            Err(format!("unterminated debug block ({}) in {}", begin, file))
+        } else {
+            Ok(res)
+        }
+    }
+}
+
+/// Path in an `#include "path"` line.
+fn include_path(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = rest
+        .strip_prefix("include")?
+        .trim_start()
+        .strip_prefix('"')?;
+    Some(&rest[..rest.find('"')?])
+}
+
+/// Visibility (eg. `pub`, possibly empty) and name in a `mod name;` line.
+fn mod_decl(line: &str) -> Option<(&str, &str)> {
+    let decl = line.trim().strip_suffix(';')?;
+    let i = decl.find("mod ")?;
+    let (vis, name) = (decl[..i].trim(), decl[i + 4..].trim());
+    let valid_vis = vis.is_empty() || vis.starts_with("pub");
+    let valid_name = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid_vis && valid_name {
+        Some((vis, name))
+    } else {
+        None
+    }
+}
+
+struct Inliner<'a> {
+    pp: &'a Preprocessor,
+    syntax: Syntax,
+    /// Files which have already been inlined (each header is only inlined
+    /// once, as if it had include guards).
+    seen: HashSet<PathBuf>,
+}
+
+impl Inliner<'_> {
+    fn process(
+        &mut self,
+        src: &str,
+        file: Option<&Path>,
+        root: bool,
+    ) -> Result<String, String> {
+        let name = file.map_or("stdin".into(), |f| f.display().to_string());
+        let src = self.pp.strip_debug(src, &name)?;
+        if !self.pp.inline {
+            return Ok(src);
+        }
+        let dir = file
+            .and_then(|f| f.parent())
+            .filter(|d| !d.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let mut res = String::new();
+        for line in src.split_inclusive('\n') {
+            let inlined = match self.syntax {
+                Syntax::C => self.inline_include(line, dir)?,
+                Syntax::Rust => self.inline_mod(line, dir, file, root)?,
+                _ => None,
+            };
+            match inlined {
+                Some(s) => res += &s,
+                None if !root && line.trim() == "#pragma once" => {}
+                None => res += line,
+            }
+        }
+        Ok(res)
+    }
+
+    /// Find the first existing file out of `candidates`, followed by each
+    /// candidate in every include dir.
+    fn find(&self, dir: &Path, candidates: &[PathBuf]) -> Option<PathBuf> {
+        std::iter::once(dir)
+            .chain(self.pp.include_dirs.iter().map(PathBuf::as_path))
+            .flat_map(|d| candidates.iter().map(move |c| d.join(c)))
+            .find(|p| p.is_file())
+    }
+
+    /// Contents of a file to be inlined, or None if it was inlined already.
+    fn read(&mut self, path: &Path) -> Result<Option<String>, String> {
+        let canonical =
+            fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if !self.seen.insert(canonical) {
+            return Ok(None);
+        }
+        eprintln!("inlining {}", path.display());
+        match fs::read_to_string(path) {
+            Ok(s) => Ok(Some(s)),
+            Err(e) => Err(format!("unable to read {}: {}", path.display(), e)),
+        }
+    }
+
+    fn inline_include(
+        &mut self,
+        line: &str,
+        dir: &Path,
+    ) -> Result<Option<String>, String> {
+        let include = match include_path(line) {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+        let path = match self.find(dir, &[PathBuf::from(include)]) {
+            Some(p) => p,
+            // eg. a system header included with quotes
+            None => return Ok(None),
+        };
+        match self.read(&path)? {
+            Some(s) => {
+                let mut s = self.process(&s, Some(&path), false)?;
+                if !s.ends_with('\n') {
+                    s.push('\n');
+                }
+                Ok(Some(s))
+            }
+            None => Ok(Some(String::new())),
+        }
+    }
+
+    fn inline_mod(
+        &mut self,
+        line: &str,
+        dir: &Path,
+        file: Option<&Path>,
+        root: bool,
+    ) -> Result<Option<String>, String> {
+        let (vis, name) = match mod_decl(line) {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+        // Submodules of `foo.rs` live in `foo/`, except for the crate root
+        // and `mod.rs` files.
+        let mut dir = dir.to_path_buf();
+        if let Some(f) = file.filter(|f| !root && !f.ends_with("mod.rs")) {
+            dir = dir.join(f.file_stem().unwrap_or_default());
+        }
+        let candidates = [
+            PathBuf::from(format!("{}.rs", name)),
+            Path::new(name).join("mod.rs"),
+        ];
+        let path = match self.find(&dir, &candidates) {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        match self.read(&path)? {
+            Some(s) => {
+                let s = self.process(&s, Some(&path), false)?;
+                let vis = if vis.is_empty() {
+                    String::new()
+                } else {
+                    format!("{} ", vis)
+                };
+                Ok(Some(format!(
+                    "{}mod {} {{\n{}{}}}\n",
+                    vis,
+                    name,
+                    s,
+                    if s.ends_with('\n') { "" } else { "\n" }
+                )))
+            }
+            None => Err(format!(
+                "module {} declared more than once ({} was already inlined)",
+                name,
+                path.display()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    fn preprocessor(include_dirs: Vec<PathBuf>) -> Preprocessor {
+        Preprocessor {
+            inline: true,
+            include_dirs,
+            debug_markers: None,
+            random_comment: false,
+        }
+    }
+
+    /// Write each file (within a fresh directory), returning the directory.
+    fn write_files(files: &[(&str, &str)]) -> PathBuf {
+        let dir = testutil::proj_dirs().cache_dir().join("src");
+        for (name, contents) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn inline(
+        pp: &Preprocessor,
+        dir: &Path,
+        main: &str,
+    ) -> Result<String, String> {
+        let file = dir.join(main);
+        let src = fs::read_to_string(&file).unwrap();
+        pp.apply(&src, Some(&file), None)
+    }
+
+    #[test]
+    fn inlines_includes() {
+        let _dirs = testutil::isolated_dirs();
+        let lib = testutil::proj_dirs().cache_dir().join("lib");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("dsu.h"), "struct dsu {};").unwrap();
+        let dir = write_files(&[
+            (
+                "a.cpp",
+                "#pragma once\n#include <bits/stdc++.h>\n#include \"a.h\"\n\
+                # include \"b.h\"\n#include \"dsu.h\"\n#include \"missing.h\"\n\
+                int main() {}\n",
+            ),
+            ("a.h", "#pragma once\n#include \"b.h\"\nint a;\n"),
+            (
+                "b.h",
+                "#pragma once\n#include \"a.h\"\n#include \"a.cpp\"\nint b;\n",
+            ),
+        ]);
+        let res = inline(&preprocessor(vec![lib]), &dir, "a.cpp").unwrap();
+        assert_eq!(
+            res,
+            "#pragma once\n#include <bits/stdc++.h>\nint b;\nint a;\n\
+            struct dsu {};\n#include \"missing.h\"\nint main() {}\n"
+        );
+    }
+
+    #[test]
+    fn include_cycles() {
+        let _dirs = testutil::isolated_dirs();
+        let dir = write_files(&[
+            ("a.cpp", "#include \"x.h\"\n#include \"x.h\"\nmain\n"),
+            ("x.h", "#include \"y.h\"\nx\n"),
+            ("y.h", "#include \"z/../x.h\"\n#include \"y.h\"\ny\n"),
+            ("z/.keep", ""),
+        ]);
+        let res = inline(&preprocessor(vec![]), &dir, "a.cpp").unwrap();
+        assert_eq!(res, "y\nx\nmain\n");
+    }
+
+    #[test]
+    fn inlining_disabled() {
+        let _dirs = testutil::isolated_dirs();
+        let dir = write_files(&[("a.cpp", "#include \"a.h\"\n"), ("a.h", "")]);
+        let mut pp = preprocessor(vec![]);
+        pp.inline = false;
+        assert_eq!(inline(&pp, &dir, "a.cpp").unwrap(), "#include \"a.h\"\n");
+    }
+
+    #[test]
+    fn inlines_mods() {
+        let _dirs = testutil::isolated_dirs();
+        let dir = write_files(&[
+            (
+                "main.rs",
+                "mod io;\npub(crate) mod graph;\nmod missing;\nmod inline {}\n\
+                fn main() {}\n",
+            ),
+            ("io.rs", "pub fn read() {}"),
+            ("graph/mod.rs", "pub mod dsu;\n"),
+            ("graph/dsu.rs", "mod util;\npub struct Dsu;\n"),
+            ("graph/dsu/util.rs", "fn f() {}\n"),
+        ]);
+        let res = inline(&preprocessor(vec![]), &dir, "main.rs").unwrap();
+        assert_eq!(
+            res,
+            "mod io {\npub fn read() {}\n}\npub(crate) mod graph {\n\
+            pub mod dsu {\nmod util {\nfn f() {}\n}\npub struct Dsu;\n}\n}\n\
+            mod missing;\nmod inline {}\nfn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn mod_declared_twice() {
+        let _dirs = testutil::isolated_dirs();
+        let dir = write_files(&[("main.rs", "mod a;\nmod a;\n"), ("a.rs", "")]);
+        let e = inline(&preprocessor(vec![]), &dir, "main.rs").unwrap_err();
+        assert!(e.starts_with("module a declared more than once ("), "{}", e);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mod_cycles() {
+        let _dirs = testutil::isolated_dirs();
+        let dir = write_files(&[("main.rs", "mod a;\n"), ("a.rs", "mod b;\n")]);
+        fs::create_dir(dir.join("a")).unwrap();
+        std::os::unix::fs::symlink("../a.rs", dir.join("a/b.rs")).unwrap();
+        let e = inline(&preprocessor(vec![]), &dir, "main.rs").unwrap_err();
+        assert!(e.starts_with("module b declared more than once ("), "{}", e);
+        assert!(e.ends_with("a/b.rs was already inlined)"), "{}", e);
+    }
+
+    #[test]
+    fn strips_debug_blocks() {
+        let mut pp = preprocessor(vec![]);
+        pp.inline = false;
+        pp.debug_markers = Some(("DEBUG-BEGIN".into(), "DEBUG-END".into()));
+        let src = "a\n// DEBUG-BEGIN\nb\n// DEBUG-END\nc\n";
+        assert_eq!(pp.apply(src, None, None).unwrap(), "a\nc\n");
+        assert!(pp.apply("a\n// DEBUG-BEGIN\nb\n", None, None).is_err());
+    }
+
+    #[test]
+    fn random_comments() {
+        let mut pp = preprocessor(vec![]);
+        pp.inline = false;
+        pp.random_comment = true;
+        for (file, language, prefix) in [
+            (Some("a.cpp"), None, "// "),
+            (Some("a.py"), None, "# "),
+            (Some("a.hs"), None, "-- "),
+            (Some("a.ml"), None, "(* "),
+            (None, Some("Python 3.8"), "# "),
+            (None, Some("Kotlin 1.7"), "// "),
+        ] {
+            let res = pp.apply("x", file.map(Path::new), language).unwrap();
+            let comment = res.strip_prefix("x\n").unwrap();
+            assert!(comment.starts_with(prefix), "{}", res);
+            assert_ne!(
+                res,
+                pp.apply("x", file.map(Path::new), language).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use clap::ArgMatches;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use crate::handlers::get_optional_args_of_type;
+use crate::output::{cell, lookup};
+
+/// Comparison operators supported in `--filter` expressions. Two character
+/// operators are listed first so that they are matched before their prefixes.
+const OPS: &[(&str, Op)] = &[
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("!~", Op::NotContains),
+    ("=", Op::Eq),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+    ("~", Op::Contains),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    NotContains,
+}
+
+/// A single `--filter` predicate of the form `<path><op><value>`, eg.
+/// `verdict!=OK`, `problem.rating>=1900` or `problem.tags~dp`.
+pub struct Filter {
+    path: String,
+    op: Op,
+    value: String,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        for (i, _) in s.char_indices() {
+            if let Some((tok, op)) =
+                OPS.iter().find(|(tok, _)| s[i..].starts_with(tok))
+            {
+                let path = s[..i].trim();
+                if path.is_empty() {
+                    break;
+                }
+                return Ok(Filter {
+                    path: path.to_string(),
+                    op: *op,
+                    value: s[i + tok.len()..].trim().to_string(),
+                });
+            }
+        }
+        Err(format!(
+            "invalid filter `{}` (expected <field><op><value> where op is \
+            one of =, !=, <, <=, >, >=, ~, !~)",
+            s
+        ))
+    }
+}
+
+impl Filter {
+    /// Check whether a record satisfies this predicate. Missing fields are
+    /// treated as empty strings. If both sides are numbers then they are
+    /// compared numerically, otherwise as strings.
+    pub fn matches(&self, record: &Value) -> bool {
+        let lhs = cell(lookup(record, &self.path));
+        let rhs = &self.value;
+        let ord = match (lhs.parse::<f64>(), rhs.parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b),
+            _ => Some(lhs.as_str().cmp(rhs.as_str())),
+        };
+        match self.op {
+            Op::Eq => ord == Some(Ordering::Equal),
+            Op::Ne => ord != Some(Ordering::Equal),
+            Op::Lt => ord == Some(Ordering::Less),
+            Op::Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+            Op::Gt => ord == Some(Ordering::Greater),
+            Op::Ge => {
+                matches!(ord, Some(Ordering::Greater | Ordering::Equal))
+            }
+            Op::Contains => lhs.contains(rhs.as_str()),
+            Op::NotContains => !lhs.contains(rhs.as_str()),
+        }
+    }
+}
+
+/// Field selection (`--fields`) and filtering (`--filter`) requested on the
+/// command line.
+pub struct Query {
+    pub fields: Option<Vec<String>>,
+    pub filters: Vec<Filter>,
+}
+
+impl Query {
+    pub fn from_args(args: &ArgMatches) -> Query {
+        Query {
+            fields: get_optional_args_of_type(args, "fields"),
+            filters: get_optional_args_of_type(args, "filter")
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_none() && self.filters.is_empty()
+    }
+
+    /// Filter and project the given records. Projected records keep the
+    /// nesting of the original fields so that they can be looked up with the
+    /// same paths.
+    pub fn apply(&self, records: Vec<Value>) -> Vec<Value> {
+        records
+            .into_iter()
+            .filter(|r| self.filters.iter().all(|f| f.matches(r)))
+            .map(|r| match self.fields {
+                Some(ref fields) => project(&r, fields),
+                None => r,
+            })
+            .collect()
+    }
+}
+
+fn project(record: &Value, fields: &[String]) -> Value {
+    let mut res = Value::Object(Map::new());
+    for f in fields {
+        let v = lookup(record, f).cloned().unwrap_or(Value::Null);
+        insert(&mut res, f, v);
+    }
+    res
+}
+
+fn insert(target: &mut Value, path: &str, v: Value) {
+    let mut cur = target;
+    let mut keys = path.split('.').peekable();
+    while let Some(k) = keys.next() {
+        if !cur.is_object() {
+            *cur = Value::Object(Map::new());
+        }
+        // unwrap is fine since cur was made an object above
+        let m = cur.as_object_mut().unwrap();
+        if keys.peek().is_none() {
+            m.insert(k.to_string(), v);
+            return;
+        }
+        cur = m.entry(k).or_insert(Value::Null);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_filters() {
+        for (s, path, op, value) in [
+            ("verdict=OK", "verdict", Op::Eq, "OK"),
+            ("verdict==OK", "verdict", Op::Eq, "OK"),
+            ("verdict!=OK", "verdict", Op::Ne, "OK"),
+            (" verdict != OK ", "verdict", Op::Ne, "OK"),
+            ("problem.rating<1900", "problem.rating", Op::Lt, "1900"),
+            ("problem.rating<=1900", "problem.rating", Op::Le, "1900"),
+            ("problem.rating>1900", "problem.rating", Op::Gt, "1900"),
+            ("problem.rating>=1900", "problem.rating", Op::Ge, "1900"),
+            ("problem.tags~dp", "problem.tags", Op::Contains, "dp"),
+            ("problem.tags!~dp", "problem.tags", Op::NotContains, "dp"),
+            // the first operator splits the filter, the rest is the value
+            ("name=a!=b", "name", Op::Eq, "a!=b"),
+            ("name!=a=b", "name", Op::Ne, "a=b"),
+            ("name~<=", "name", Op::Contains, "<="),
+            ("name=", "name", Op::Eq, ""),
+            ("a!b=c", "a!b", Op::Eq, "c"),
+        ] {
+            let f: Filter = s.parse().unwrap();
+            assert_eq!(
+                (f.path.as_str(), f.op, f.value.as_str()),
+                (path, op, value),
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_filters() {
+        for s in ["", "verdict", "=OK", "!=OK", " ~dp"] {
+            assert!(s.parse::<Filter>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn matches_records() {
+        let r = json!({
+            "verdict": "OK",
+            "problem": {"rating": 1900, "tags": ["dp", "greedy"]},
+            "points": null,
+        });
+        for (s, expected) in [
+            ("verdict=OK", true),
+            ("verdict!=OK", false),
+            ("verdict!=WRONG_ANSWER", true),
+            ("problem.rating=1900.0", true),
+            ("problem.rating<2000", true),
+            ("problem.rating<=1900", true),
+            ("problem.rating>=2000", false),
+            ("problem.rating>800", true),
+            ("problem.tags~greedy", true),
+            ("problem.tags!~dp", false),
+            ("problem.tags=dp;greedy", true),
+            ("points=", true),
+            ("missing=", true),
+            ("missing!=", false),
+            ("verdict<P", true),
+        ] {
+            let f: Filter = s.parse().unwrap();
+            assert_eq!(f.matches(&r), expected, "{}", s);
+        }
+    }
+
+    #[test]
+    fn applies_queries() {
+        let q = Query {
+            fields: Some(vec!["id".into(), "problem.index".into()]),
+            filters: vec!["verdict=OK".parse().unwrap()],
+        };
+        let records = vec![
+            json!({"id": 1, "verdict": "OK", "problem": {"index": "A"}}),
+            json!({"id": 2, "verdict": "WA", "problem": {"index": "B"}}),
+        ];
+        assert_eq!(
+            q.apply(records),
+            [json!({"id": 1, "problem": {"index": "A"}})]
+        );
+    }
+}
@@ -0,0 +1,403 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::checker::{Checker, CheckerFiles};
+use crate::config::{Config, LanguageConfig};
+use crate::workspace::{render, WorkspaceSettings};
+
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+
+/// Default time limit (in seconds) for each testcase.
+pub const DEFAULT_TIME_LIMIT: f64 = 2.0;
+
+/// Default compile and run commands for some common languages, keyed by
+/// programTypeId. Languages which aren't listed must be configured in the
+/// `languages` map in config.yml.
+const DEFAULT_LANGUAGES: &[(i64, Option<&str>, &str)] = &[
+    (43, Some("gcc -std=c11 -O2 -o <exe> <file> -lm"), "<exe>"),
+    (42, Some("g++ -std=c++11 -O2 -o <exe> <file>"), "<exe>"),
+    (50, Some("g++ -std=c++14 -O2 -o <exe> <file>"), "<exe>"),
+    (52, Some("clang++ -std=c++17 -O2 -o <exe> <file>"), "<exe>"),
+    (54, Some("g++ -std=c++17 -O2 -o <exe> <file>"), "<exe>"),
+    (61, Some("g++ -std=c++17 -O2 -o <exe> <file>"), "<exe>"),
+    (28, Some("dmd -O -of=<exe> <file>"), "<exe>"),
+    (32, Some("go build -o <exe> <file>"), "<exe>"),
+    (12, Some("ghc -O2 -o <exe> <file>"), "<exe>"),
+    (49, Some("rustc -O -o <exe> <file>"), "<exe>"),
+    (13, None, "perl <file>"),
+    (6, None, "php <file>"),
+    (7, None, "python2 <file>"),
+    (31, None, "python3 <file>"),
+    (40, None, "pypy <file>"),
+    (41, None, "pypy3 <file>"),
+    (67, None, "ruby <file>"),
+    (34, None, "node <file>"),
+    (55, None, "node <file>"),
+];
+
+/// Verdict of running a solution on a single testcase. Named after the
+/// verdicts given by Codeforces.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Ok,
+    WrongAnswer,
+    TimeLimitExceeded,
+    RuntimeError,
+    /// The solution ran successfully but there is no expected output to
+    /// compare against.
+    Unchecked,
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Verdict::Ok => "OK",
+            Verdict::WrongAnswer => "WRONG_ANSWER",
+            Verdict::TimeLimitExceeded => "TIME_LIMIT_EXCEEDED",
+            Verdict::RuntimeError => "RUNTIME_ERROR",
+            Verdict::Unchecked => "UNCHECKED",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Output of a single run of a solution.
+pub struct RunOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub elapsed: Duration,
+    /// Exit status, or `None` if the time limit was exceeded.
+    pub status: Option<std::process::ExitStatus>,
+}
+
+/// A sample testcase stored on disk.
+pub struct TestcaseFiles {
+    pub num: usize,
+    pub input: PathBuf,
+    pub output: Option<PathBuf>,
+}
+
+/// Look up the commands for a programTypeId, preferring config.yml over the
+/// defaults.
+pub fn language(conf: &Config, id: i64) -> Result<LanguageConfig, String> {
+    let configured = conf.languages.as_ref().and_then(|m| m.get(&id));
+    if let Some(l) = configured {
+        return Ok(l.clone());
+    }
+    DEFAULT_LANGUAGES
+        .iter()
+        .find(|(i, _, _)| *i == id)
+        .map(|(_, compile, run)| LanguageConfig {
+            compile: compile.map(String::from),
+            run: run.to_string(),
+        })
+        .ok_or_else(|| {
+            format!(
+                "no commands known for programTypeId {}, add them to the \
+                `languages` map in config.yml",
+                id
+            )
+        })
+}
+
+/// Quote a value so that it can be substituted into a shell command.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Substitute the `<file>`, `<exe>` and `<dir>` placeholders of a command.
+pub fn command_for(pattern: &str, file: &Path, exe: &Path) -> String {
+    let dir = file
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let file = shell_quote(&file.to_string_lossy());
+    let exe = shell_quote(&exe.to_string_lossy());
+    let dir = shell_quote(&dir.to_string_lossy());
+    render(pattern, &[("file", &file), ("exe", &exe), ("dir", &dir)])
+}
+
+/// Path of the executable built for `file` (placed in the temp dir).
+pub fn exe_path(file: &Path) -> PathBuf {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    std::env::temp_dir().join(format!(
+        "caffeine-{}-{}",
+        std::process::id(),
+        stem
+    ))
+}
+
+/// Run the compile command (if any), returning the compiler's output as an
+/// error if it fails.
+pub fn compile(cmd: &str) -> Result<(), String> {
+    let out = Command::new("sh")
+        .args(["-c", cmd])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("unable to run `{}`: {}", cmd, e))?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "compilation failed ({}):\n{}{}",
+            out.status,
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&out.stderr)
+        ))
+    }
+}
+
+/// Whether a command run by [`run`] has exited. It isn't reaped, so that its
+/// process group can't have been reused when it's killed.
+#[cfg(unix)]
+fn exited(child: &mut Child) -> std::io::Result<bool> {
+    let flags = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+    // safe since siginfo_t is plain data, which waitid fills in
+    let pid = unsafe {
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        if libc::waitid(libc::P_PID, child.id(), &mut info, flags) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        info.si_pid()
+    };
+    Ok(pid != 0)
+}
+
+#[cfg(not(unix))]
+fn exited(child: &mut Child) -> std::io::Result<bool> {
+    child.try_wait().map(|s| s.is_some())
+}
+
+/// Kill a command run by [`run`] along with anything it started, which is in
+/// the same process group (eg. the solution run by `cd dir && ./sol`).
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    // safe since kill only sends a signal (to the child's own process group,
+    // which exists until the child is reaped)
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    let _ = child.kill();
+}
+
+/// Run a command with `input` on stdin, killing it once `time_limit` has
+/// passed.
+pub fn run(
+    cmd: &str,
+    input: &str,
+    time_limit: Duration,
+) -> Result<RunOutput, String> {
+    let mut command = Command::new("sh");
+    command
+        .args(["-c", cmd])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // a process group of its own, so that everything it runs can be killed
+    #[cfg(unix)]
+    command.process_group(0);
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("unable to run `{}`: {}", cmd, e))?;
+    let start = Instant::now();
+
+    // stdin/stdout/stderr are handled on separate threads so that large
+    // inputs or outputs can't cause a deadlock.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_string();
+    std::thread::spawn(move || {
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let mut stdout = child.stdout.take().unwrap();
+    let out = std::thread::spawn(move || {
+        let mut s = Vec::new();
+        let _ = stdout.read_to_end(&mut s);
+        s
+    });
+    let mut stderr = child.stderr.take().unwrap();
+    let err = std::thread::spawn(move || {
+        let mut s = Vec::new();
+        let _ = stderr.read_to_end(&mut s);
+        s
+    });
+
+    loop {
+        match exited(&mut child) {
+            Ok(true) => {
+                let elapsed = start.elapsed();
+                // anything left running in the background could otherwise
+                // keep stdout open
+                kill(&mut child);
+                let status = child.wait().map_err(|e| {
+                    format!("failed waiting for solution: {}", e)
+                })?;
+                let stdout = out.join().unwrap_or_default();
+                let stderr = err.join().unwrap_or_default();
+                return Ok(RunOutput {
+                    stdout: String::from_utf8_lossy(&stdout).into(),
+                    stderr: String::from_utf8_lossy(&stderr).into(),
+                    elapsed,
+                    status: Some(status),
+                });
+            }
+            Ok(false) if start.elapsed() > time_limit => {
+                kill(&mut child);
+                let _ = child.wait();
+                return Ok(RunOutput {
+                    stdout: String::new(),
+                    stderr: String::new(),
+                    elapsed: start.elapsed(),
+                    status: None,
+                });
+            }
+            Ok(false) => std::thread::sleep(Duration::from_millis(5)),
+            Err(e) => {
+                return Err(format!("failed waiting for solution: {}", e))
+            }
+        }
+    }
+}
+
+/// Judge a single run of a solution, returning its verdict along with any
+/// message given by the checker.
+pub fn judge(
+    out: &RunOutput,
+    t: &TestcaseFiles,
+    expected: Option<&str>,
+    checker: &Checker,
+) -> Result<(Verdict, Option<String>), String> {
+    match (out.status, expected, &t.output) {
+        (None, _, _) => Ok((Verdict::TimeLimitExceeded, None)),
+        (Some(s), _, _) if !s.success() => Ok((Verdict::RuntimeError, None)),
+        (_, Some(e), Some(answer)) => {
+            let files = CheckerFiles {
+                input: &t.input,
+                answer,
+            };
+            let (ok, msg) = checker.check(e, &out.stdout, &files)?;
+            let v = if ok {
+                Verdict::Ok
+            } else {
+                Verdict::WrongAnswer
+            };
+            Ok((v, msg))
+        }
+        _ => Ok((Verdict::Unchecked, None)),
+    }
+}
+
+/// Choose the checker for a problem: the one given on the command line, else
+/// the one in the problem's checker file, else the default in config.yml.
+/// Custom checker paths are relative to the problem's directory `dir`.
+pub fn find_checker(
+    cli: Option<Checker>,
+    dir: &Path,
+    problem: &str,
+    settings: &WorkspaceSettings,
+    conf: &Config,
+) -> Result<Checker, String> {
+    if let Some(c) = cli {
+        return Ok(c.relative_to(dir));
+    }
+    let (lower, upper) = (problem.to_lowercase(), problem.to_uppercase());
+    let vars = [("problem", &*lower), ("PROBLEM", &*upper)];
+    let path = dir.join(render(&settings.checker_filename, &vars));
+    if let Some(c) = Checker::from_file(&path)? {
+        return Ok(c);
+    }
+    match conf.checker {
+        Some(ref c) => c
+            .parse::<Checker>()
+            .map(|c| c.relative_to(dir))
+            .map_err(|e| format!("{} (in config.yml)", e)),
+        None => Ok(Checker::default()),
+    }
+}
+
+/// Problem index taken from the name of a solution file (eg. `b.cpp` is
+/// problem b).
+pub fn problem_from_file(file: &Path) -> String {
+    file.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into()
+}
+
+/// Find the testcases stored for a problem in `dir` using the testcase
+/// filename patterns of the workspace.
+pub fn find_testcases(
+    dir: &Path,
+    problem: &str,
+    settings: &WorkspaceSettings,
+) -> Vec<TestcaseFiles> {
+    let (lower, upper) = (problem.to_lowercase(), problem.to_uppercase());
+    let mut res = vec![];
+    for num in 1.. {
+        let n = num.to_string();
+        let vars = [("problem", &*lower), ("PROBLEM", &*upper), ("num", &*n)];
+        let input = dir.join(render(&settings.input_filename, &vars));
+        if !input.is_file() {
+            break;
+        }
+        let output = dir.join(render(&settings.output_filename, &vars));
+        res.push(TestcaseFiles {
+            num,
+            input,
+            output: if output.is_file() { Some(output) } else { None },
+        });
+    }
+    res
+}
+
+/// Read a testcase file, mapping errors to a message.
+pub fn read_file(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map_err(|e| format!("unable to read {}: {}", path.display(), e))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    const LIMIT: Duration = Duration::from_secs(2);
+
+    #[test]
+    fn runs_shell_commands() {
+        for (cmd, expected, code) in [
+            ("cat", "in\n", 0),
+            ("read x; echo \"$x$x\"", "inin\n", 0),
+            ("cd / && pwd", "/\n", 0),
+            ("true && tr a-z A-Z", "IN\n", 0),
+            ("exit 3", "", 3),
+        ] {
+            let out = run(cmd, "in\n", LIMIT).unwrap();
+            assert_eq!(out.stdout, expected, "{}", cmd);
+            assert_eq!(out.status.unwrap().code(), Some(code), "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn kills_everything_after_time_limit() {
+        let limit = Duration::from_millis(200);
+        for cmd in ["sleep 5", "cd / && sleep 5", "sleep 5; echo done"] {
+            let start = Instant::now();
+            let out = run(cmd, "", limit).unwrap();
+            assert!(out.status.is_none(), "{}", cmd);
+            assert!(start.elapsed() < Duration::from_secs(2), "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn doesnt_wait_for_background_processes() {
+        let start = Instant::now();
+        let out = run("sleep 5 & echo hi", "", LIMIT).unwrap();
+        assert_eq!(out.stdout, "hi\n");
+        assert!(out.status.unwrap().success());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,258 @@
+use directories::ProjectDirs;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::config::Config;
+use crate::permissions;
+
+pub const STORE_NAMES: &[&str] = &["secret-service", "file"];
+
+/// Program used to talk to the Secret Service (part of libsecret).
+const DEFAULT_SECRET_TOOL: &str = "secret-tool";
+
+/// Somewhere to keep the API secret and password rather than in auth.yml,
+/// which then only stores the name of the store (see `secret_store` in
+/// config.yml). Secrets are identified by an account name (eg.
+/// `password:thud`).
+pub trait SecretStore {
+    /// Name of the store as used in config.yml and auth.yml.
+    fn name(&self) -> &'static str;
+    /// The secret stored for `account` (None if there isn't one).
+    fn get(&self, account: &str) -> Result<Option<String>, String>;
+    /// Store a secret, replacing any previous secret for `account`.
+    fn set(&self, account: &str, secret: &str) -> Result<(), String>;
+}
+
+/// The store named `name`. `secret_tool` in config.yml replaces the program
+/// used for the Secret Service (eg. with a stand-in on machines without a
+/// desktop session).
+pub fn open(name: &str, conf: &Config) -> Result<Box<dyn SecretStore>, String> {
+    match name {
+        "secret-service" => Ok(Box::new(SecretService {
+            program: conf
+                .secret_tool
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SECRET_TOOL.to_string()),
+        })),
+        "file" => Ok(Box::new(FileStore)),
+        _ => Err(format!(
+            "unknown secret store `{}` (expected one of: {})",
+            name,
+            STORE_NAMES.join(", ")
+        )),
+    }
+}
+
+/// The Secret Service (eg. GNOME Keyring or KWallet), through libsecret's
+/// `secret-tool`.
+struct SecretService {
+    program: String,
+}
+
+impl SecretService {
+    fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(args)
+            .args(["service", crate::NAME_BIN, "account"])
+            .stderr(Stdio::piped());
+        cmd
+    }
+
+    fn error(&self, e: std::io::Error) -> String {
+        if e.kind() == ErrorKind::NotFound {
+            format!(
+                "{} not found, install libsecret (or set secret_store: file \
+                in config.yml)",
+                self.program
+            )
+        } else {
+            format!("unable to run {}: {}", self.program, e)
+        }
+    }
+}
+
+impl SecretStore for SecretService {
+    fn name(&self) -> &'static str {
+        "secret-service"
+    }
+
+    fn get(&self, account: &str) -> Result<Option<String>, String> {
+        let out = self
+            .command(&["lookup"])
+            .arg(account)
+            .output()
+            .map_err(|e| self.error(e))?;
+        // secret-tool exits with 1 (and prints nothing) for missing secrets
+        if !out.status.success() && out.stderr.is_empty() {
+            return Ok(None);
+        }
+        if !out.status.success() {
+            return Err(format!(
+                "{} lookup failed: {}",
+                self.program,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ));
+        }
+        let secret = String::from_utf8_lossy(&out.stdout);
+        let secret = secret.strip_suffix('\n').unwrap_or(&secret);
+        Ok(Some(secret.to_string()).filter(|s| !s.is_empty()))
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        let label = format!("{} ({})", crate::NAME_BIN, account);
+        let mut child = self
+            .command(&["store", "--label", &label])
+            .arg(account)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| self.error(e))?;
+        // unwrap is fine since stdin was piped
+        let res = child.stdin.take().unwrap().write_all(secret.as_bytes());
+        let out = child.wait_with_output().map_err(|e| self.error(e))?;
+        if let Err(e) = res {
+            return Err(self.error(e));
+        }
+        if out.status.success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} store failed: {}",
+                self.program,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ))
+        }
+    }
+}
+
+/// Fallback for machines without a Secret Service: a separate file in the
+/// data dir which only the user can read.
+struct FileStore;
+
+impl FileStore {
+    fn path() -> Result<PathBuf, String> {
+        ProjectDirs::from(crate::NAME_QUL, crate::NAME_ORG, crate::NAME_BIN)
+            .map(|proj_dirs| {
+                proj_dirs.data_dir().join(crate::SECRETS_FILE_NAME)
+            })
+            .ok_or_else(|| "couldn't find a valid path to store keys".into())
+    }
+
+    fn read() -> Result<BTreeMap<String, String>, String> {
+        match fs::read_to_string(FileStore::path()?) {
+            Ok(s) => serde_yaml::from_str(&s).map_err(|_| {
+                format!("failed to parse {}", crate::SECRETS_FILE_NAME)
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(format!(
+                "could not read {}: {}",
+                crate::SECRETS_FILE_NAME,
+                e
+            )),
+        }
+    }
+}
+
+impl SecretStore for FileStore {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn get(&self, account: &str) -> Result<Option<String>, String> {
+        Ok(FileStore::read()?.remove(account))
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        let mut secrets = FileStore::read()?;
+        secrets.insert(account.to_string(), secret.to_string());
+        // unwrap is probably ok here since serializing errors are very rare
+        let s = serde_yaml::to_string(&secrets).unwrap();
+        permissions::write_private_file(&FileStore::path()?, &s).map_err(|e| {
+            format!("could not write {}: {}", crate::SECRETS_FILE_NAME, e)
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    fn secret_service(program: &std::path::Path) -> Box<dyn SecretStore> {
+        let conf = Config {
+            secret_tool: Some(program.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        open("secret-service", &conf).unwrap()
+    }
+
+    #[test]
+    fn secret_service_round_trip() {
+        let _dirs = testutil::isolated_dirs();
+        let store = secret_service(&testutil::secret_tool_stub());
+        assert_eq!(store.name(), "secret-service");
+        assert_eq!(store.get("password:thud").unwrap(), None);
+        store.set("password:thud", "p@ss word\\\"'").unwrap();
+        store.set("api-secret:abc", "s3cret").unwrap();
+        assert_eq!(
+            store.get("password:thud").unwrap().as_deref(),
+            Some("p@ss word\\\"'")
+        );
+        store.set("password:thud", "changed").unwrap();
+        assert_eq!(
+            store.get("password:thud").unwrap().as_deref(),
+            Some("changed")
+        );
+        assert_eq!(
+            store.get("api-secret:abc").unwrap().as_deref(),
+            Some("s3cret")
+        );
+        assert_eq!(store.get("password:other").unwrap(), None);
+    }
+
+    #[test]
+    fn secret_service_errors() {
+        let _dirs = testutil::isolated_dirs();
+        let failing = testutil::write_script(
+            "failing-secret-tool",
+            "echo 'no Secret Service running' >&2; exit 1\n",
+        );
+        let store = secret_service(&failing);
+        let e = store.get("password:thud").unwrap_err();
+        assert!(e.contains("no Secret Service running"), "{}", e);
+        assert!(store.set("password:thud", "x").is_err());
+
+        let missing = testutil::proj_dirs().data_dir().join("no-such-tool");
+        let e = secret_service(&missing).get("password:thud").unwrap_err();
+        assert!(e.contains("not found"), "{}", e);
+    }
+
+    #[test]
+    fn file_store_round_trip() {
+        use std::os::unix::fs::PermissionsExt;
+        let _dirs = testutil::isolated_dirs();
+        let store = open("file", &Config::default()).unwrap();
+        assert_eq!(store.get("password:thud").unwrap(), None);
+        store.set("password:thud", "p@ss").unwrap();
+        store.set("api-secret:abc", "s3cret").unwrap();
+        assert_eq!(
+            store.get("password:thud").unwrap().as_deref(),
+            Some("p@ss")
+        );
+        assert_eq!(
+            store.get("api-secret:abc").unwrap().as_deref(),
+            Some("s3cret")
+        );
+        let path = FileStore::path().unwrap();
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn unknown_store() {
+        assert!(open("keychain", &Config::default()).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use directories::ProjectDirs;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::permissions;
+
+/// Cookies of a logged in session on one site (codeforces.com or a mirror).
+#[derive(Serialize, Deserialize)]
+struct StoredSession {
+    handle: String,
+    cookies: BTreeMap<String, String>,
+}
+
+/// Stored sessions, keyed by base url.
+type Sessions = BTreeMap<String, StoredSession>;
+
+fn session_path() -> Option<PathBuf> {
+    ProjectDirs::from(crate::NAME_QUL, crate::NAME_ORG, crate::NAME_BIN)
+        .map(|proj_dirs| proj_dirs.data_dir().join(crate::SESSION_FILE_NAME))
+}
+
+fn read_sessions() -> Sessions {
+    session_path()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| serde_yaml::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Whether a url is the login page, which pages requiring a login redirect
+/// to once a session has expired.
+pub fn is_login_page(url: &Url) -> bool {
+    url.path().trim_end_matches('/').ends_with("/enter")
+}
+
+/// Cookies stored for `handle` on the site at `base_url` (if any). Sessions
+/// belonging to a different handle are never reused.
+pub fn load(base_url: &str, handle: &str) -> Option<BTreeMap<String, String>> {
+    read_sessions()
+        .remove(base_url)
+        .filter(|s| s.handle == handle && !s.cookies.is_empty())
+        .map(|s| s.cookies)
+}
+
+/// Store the cookies of a logged in session, replacing any previous session
+/// for the same site.
+pub fn save(
+    base_url: &str,
+    handle: &str,
+    cookies: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    let path = match session_path() {
+        Some(p) => p,
+        None => {
+            return Err("couldn't find a valid path to store session".into())
+        }
+    };
+    let mut sessions = read_sessions();
+    sessions.insert(
+        base_url.to_string(),
+        StoredSession {
+            handle: handle.to_string(),
+            cookies: cookies.clone(),
+        },
+    );
+    // unwrap is probably ok here since serializing errors are very rare
+    let s = serde_yaml::to_string(&sessions).unwrap();
+    permissions::write_private_file(&path, &s).map_err(|e| {
+        format!("could not write {}: {}", crate::SESSION_FILE_NAME, e)
+    })
+}
+
+/// Remove every stored session. Returns whether there was anything to remove.
+pub fn clear() -> Result<bool, String> {
+    let path = match session_path() {
+        Some(p) => p,
+        None => {
+            return Err("couldn't find a valid path to store session".into())
+        }
+    };
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(format!(
+            "could not remove {}: {}",
+            crate::SESSION_FILE_NAME,
+            e
+        )),
+    }
+}
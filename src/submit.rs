@@ -1,6 +1,17 @@
+use headless_chrome::protocol::Method;
 use headless_chrome::{util::Wait, Browser, Tab};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::io::prelude::*;
 use std::io::stdin as ioStdin;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::http_submit::HttpSubmitter;
+use crate::session;
+use crate::submit_error::{self, SubmitError};
+use crate::target::Target;
 
 #[cfg(feature = "debug-screenshot")]
 use headless_chrome::protocol::page::ScreenshotFormat;
@@ -9,124 +20,257 @@ use std::fs::File;
 
 const TIMEOUT_DELAY: u64 = 10;
 
-pub fn submit_from_string(
-    src: &str,
-    contest_id: i64,
-    problem_index: &str,
+/// Base URLs of codeforces.com and its mirrors (indexed by `--mirror`).
+const MIRRORS: &[&str] = &[
+    "https://codeforces.com/",
+    "https://m1.codeforces.com/",
+    "https://m2.codeforces.com/",
+    "https://m3.codeforces.com/",
+];
+
+pub const BACKEND_NAMES: &[&str] = &["chrome", "http"];
+
+/// Method used to log in and submit solutions.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Drive a headless chromium-based browser.
+    Chrome,
+    /// Send plain HTTP requests (see `http_submit`).
+    Http,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "chrome" => Ok(Backend::Chrome),
+            "http" => Ok(Backend::Http),
+            _ => Err(format!(
+                "unknown backend `{}` (expected one of: {})",
+                s,
+                BACKEND_NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// `Network.setCookies` (not provided by `headless_chrome`), used to restore
+/// a stored session.
+#[derive(Serialize, Debug)]
+struct SetCookies {
+    cookies: Vec<CookieParam>,
+}
+
+#[derive(Serialize, Debug)]
+struct CookieParam {
+    name: String,
+    value: String,
+    url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SetCookiesReturnObject {}
+
+impl Method for SetCookies {
+    const NAME: &'static str = "Network.setCookies";
+    type ReturnObject = SetCookiesReturnObject;
+}
+
+/// Base URL for the given mirror (defaulting to codeforces.com).
+pub fn mirror_url(mirror: Option<u8>) -> &'static str {
+    MIRRORS
+        .get(mirror.unwrap_or(0) as usize)
+        .copied()
+        .unwrap_or(MIRRORS[0])
+}
+
+/// Logs in (once) and submits solutions using one of the backends, so that
+/// several solutions can be submitted in a single session.
+pub trait Submitter {
+    fn submit(
+        &mut self,
+        src: &str,
+        target: &Target,
+        problem_index: &str,
+        program_type_id: i64,
+    ) -> Result<(), SubmitError>;
+}
+
+/// Create a submitter for the site at `base_url` using the given backend.
+/// Logging in is deferred until the first submission.
+pub fn submitter(
+    backend: Backend,
+    base_url: &str,
     handle: &str,
     password: &str,
-    program_type_id: i64,
-    mirror: Option<u8>,
-) -> Result<(), String> {
-    let browser = match Browser::default() {
-        Ok(b) => b,
-        Err(e) => return Err(format!("headless_chrome: {}", e)),
-    };
+) -> Result<Box<dyn Submitter>, String> {
+    Ok(match backend {
+        Backend::Chrome => {
+            Box::new(ChromeSubmitter::new(base_url, handle, password)?)
+        }
+        Backend::Http => {
+            Box::new(HttpSubmitter::new(base_url, handle, password)?)
+        }
+    })
+}
 
-    let tab = match browser.wait_for_initial_tab() {
-        Ok(t) => t,
-        Err(e) => return Err(format!("headless_chrome: {}", e)),
-    };
-    tab.set_default_timeout(std::time::Duration::from_secs(TIMEOUT_DELAY));
+/// Submits solutions by driving a headless browser.
+pub struct ChromeSubmitter {
+    // The browser is closed when dropped, so it is kept alongside its tab.
+    _browser: Browser,
+    tab: Arc<Tab>,
+    stub: String,
+    handle: String,
+    password: String,
+    logged_in: bool,
+}
 
-    let stub = match mirror {
-        Some(1) => "https://m1.codeforces.com/",
-        Some(2) => "https://m2.codeforces.com/",
-        Some(3) => "https://m3.codeforces.com/",
-        _ => "https://codeforces.com/",
-    }
-    .to_owned();
-    let enterurl = stub.to_string() + r"enter";
-    let submiturl =
-        stub.to_string() + r"contest/" + &contest_id.to_string() + r"/submit";
-    let mysubsurl =
-        stub.to_string() + r"contest/" + &contest_id.to_string() + r"/my";
-
-    eprintln!("attempting login");
-    let res = attempt_tab_login(&tab, &enterurl, handle, password);
-    if let Err(e) = res {
-        return Err(format!("headless_chrome: {}", e));
+impl ChromeSubmitter {
+    pub fn new(
+        stub: &str,
+        handle: &str,
+        password: &str,
+    ) -> Result<ChromeSubmitter, String> {
+        let browser = match Browser::default() {
+            Ok(b) => b,
+            Err(e) => return Err(format!("headless_chrome: {}", e)),
+        };
+
+        let tab = match browser.wait_for_initial_tab() {
+            Ok(t) => t,
+            Err(e) => return Err(format!("headless_chrome: {}", e)),
+        };
+        tab.set_default_timeout(std::time::Duration::from_secs(TIMEOUT_DELAY));
+
+        Ok(ChromeSubmitter {
+            _browser: browser,
+            tab,
+            stub: stub.to_string(),
+            handle: handle.to_string(),
+            password: password.to_string(),
+            logged_in: false,
+        })
     }
-    let logged_in =
-        Wait::with_timeout(std::time::Duration::from_secs(TIMEOUT_DELAY))
-            .until(|| {
-                if tab.get_url() == stub {
-                    Some(true)
-                } else {
-                    match tab.find_element("span.error") {
-                        Ok(_) => Some(false),
-                        Err(_) => None,
-                    }
+
+    /// Restore the stored session (visiting `submiturl` to check that it
+    /// hasn't expired), else log in.
+    fn login(&mut self, submiturl: &str) -> Result<(), SubmitError> {
+        let (tab, stub) = (&self.tab, self.stub.as_str());
+        if let Some(cookies) = session::load(stub, &self.handle) {
+            match attempt_tab_restore(tab, stub, submiturl, cookies) {
+                Ok(true) => {
+                    eprintln!("reusing stored session");
+                    return Ok(());
                 }
-            });
-
-    match logged_in {
-        Ok(true) => {
-            eprintln!("login successful");
-            let res = attempt_tab_submit(
-                &tab,
-                &submiturl,
-                src,
-                problem_index,
-                program_type_id,
-            );
-            if let Err(e) = res {
-                return Err(format!("headless_chrome: {}", e));
+                Ok(false) => eprintln!("stored session expired"),
+                Err(e) => return Err(tab_failure(tab, e)),
+            }
+        }
+
+        eprintln!("attempting login");
+        let enterurl = stub.to_string() + r"enter";
+        let res =
+            attempt_tab_login(tab, &enterurl, &self.handle, &self.password);
+        if let Err(e) = res {
+            return Err(tab_failure(tab, e));
+        }
+        let res =
+            Wait::with_timeout(std::time::Duration::from_secs(TIMEOUT_DELAY))
+                .until(|| {
+                    if tab.get_url() == stub {
+                        Some(true)
+                    } else {
+                        match tab.find_element("span.error") {
+                            Ok(_) => Some(false),
+                            Err(_) => None,
+                        }
+                    }
+                });
+        match res {
+            Ok(true) => {
+                eprintln!("login successful");
+                save_tab_session(tab, stub, &self.handle);
+                Ok(())
             }
-            let mut i = 0;
-            let successful = Wait::with_timeout(
-                std::time::Duration::from_secs(TIMEOUT_DELAY),
-            )
-            .until(|| {
-                if tab.get_url() == mysubsurl {
-                    Some(true)
-                } else {
-                    match tab.find_elements(".error") {
-                        Ok(v) => {
-                            // mirrors don't show an invisible span.error
-                            // therefore they need separate handling.
-                            match mirror {
-                                Some(1..=3) => Some(false),
-                                _ => {
-                                    if v.len() > 1 {
-                                        Some(false)
-                                    } else {
-                                        None
-                                    }
+            Ok(false) => Err(SubmitError::LoginFailed),
+            Err(e) => Err(tab_failure(tab, e.into())),
+        }
+    }
+}
+
+impl Submitter for ChromeSubmitter {
+    fn submit(
+        &mut self,
+        src: &str,
+        target: &Target,
+        problem_index: &str,
+        program_type_id: i64,
+    ) -> Result<(), SubmitError> {
+        let submiturl = target.submit_url(&self.stub);
+        if !self.logged_in {
+            self.login(&submiturl)?;
+            self.logged_in = true;
+        }
+        let (tab, stub) = (&self.tab, self.stub.as_str());
+        let is_mirror = MIRRORS[1..].contains(&stub);
+
+        let res = attempt_tab_submit(
+            tab,
+            &submiturl,
+            src,
+            target.problem_field(problem_index),
+            program_type_id,
+        );
+        if let Err(e) = res {
+            return Err(tab_failure(tab, e));
+        }
+        let mut i = 0;
+        let successful =
+            Wait::with_timeout(std::time::Duration::from_secs(TIMEOUT_DELAY))
+                .until(|| {
+                    let url = Url::parse(&tab.get_url());
+                    if url.is_ok_and(|u| target.is_success_url(stub, &u)) {
+                        Some(true)
+                    } else {
+                        match tab.find_elements(".error") {
+                            Ok(v) => {
+                                // mirrors don't show an invisible span.error
+                                // therefore they need separate handling.
+                                if is_mirror || v.len() > 1 {
+                                    Some(false)
+                                } else {
+                                    None
                                 }
                             }
-                        }
-                        Err(_) => {
-                            #[cfg(feature = "debug-screenshot")]
-                            let _ = debug_screenshot(
-                                &tab,
-                                &format!("ss_waitingforres/{}.jpg", i),
-                            );
-
-                            i += 1;
-                            None
+                            Err(_) => {
+                                #[cfg(feature = "debug-screenshot")]
+                                let _ = debug_screenshot(
+                                    tab,
+                                    &format!("ss_waitingforres/{}.jpg", i),
+                                );
+
+                                i += 1;
+                                None
+                            }
                         }
                     }
-                }
-            });
+                });
 
-            #[cfg(feature = "debug-screenshot")]
-            let _ = debug_screenshot(&tab, "ss_aftersubmission.jpg");
+        #[cfg(feature = "debug-screenshot")]
+        let _ = debug_screenshot(tab, "ss_aftersubmission.jpg");
 
-            match successful {
-                Ok(true) => Ok(()),
-                Ok(false) => {
-                    Err("failed to submit problem, error on submissions page"
-                        .to_string())
-                }
-                Err(e) => Err(format!("headless_chrome: {}", e)),
+        match successful {
+            Ok(true) => {
+                save_tab_session(tab, stub, &self.handle);
+                Ok(())
             }
+            Ok(false) => Err(SubmitError::from_message(
+                &tab_page_error(tab)
+                    .unwrap_or_else(|| "error on submissions page".to_string()),
+            )),
+            Err(e) => Err(tab_failure(tab, e.into())),
         }
-        Ok(false) => {
-            Err("login unsuccessful, incorrect username or password"
-                .to_string())
-        }
-        Err(e) => Err(format!("headless_chrome: {}", e)),
     }
 }
 
@@ -148,6 +292,85 @@ pub fn grab_text_from_stdin() -> Option<String> {
     }
 }
 
+/// Set the cookies of a stored session and check whether it is still logged
+/// in by visiting a page which requires it.
+fn attempt_tab_restore(
+    tab: &Tab,
+    stub: &str,
+    url: &str,
+    cookies: BTreeMap<String, String>,
+) -> Result<bool, failure::Error> {
+    let cookies = cookies
+        .into_iter()
+        .map(|(name, value)| CookieParam {
+            name,
+            value,
+            url: stub.to_string(),
+        })
+        .collect();
+    tab.call_method(SetCookies { cookies })?;
+    tab.navigate_to(url)?.wait_until_navigated()?;
+    let url = Url::parse(&tab.get_url())?;
+    Ok(!session::is_login_page(&url))
+}
+
+/// Text of the (non-empty) errors shown on the current page.
+fn tab_page_error(tab: &Tab) -> Option<String> {
+    let errors = tab
+        .evaluate(
+            "Array.from(document.querySelectorAll('.error'))
+                .map(e => e.innerText.trim())
+                .filter(s => s)
+                .join('\\n')",
+            false,
+        )
+        .ok()?
+        .value?;
+    errors.as_str().filter(|s| !s.is_empty()).map(String::from)
+}
+
+/// Explain why the tab didn't reach the expected page (eg. a login or
+/// Cloudflare challenge page was shown instead).
+fn tab_failure(tab: &Tab, e: failure::Error) -> SubmitError {
+    let url = Url::parse(&tab.get_url());
+    if url.is_ok_and(|u| session::is_login_page(&u)) {
+        return SubmitError::LoginRequired;
+    }
+    let challenge = tab
+        .get_title()
+        .is_ok_and(|t| submit_error::is_challenge_title(&t));
+    if challenge {
+        SubmitError::Challenge
+    } else {
+        SubmitError::Other(format!("headless_chrome: {}", e))
+    }
+}
+
+fn save_tab_session(tab: &Tab, stub: &str, handle: &str) {
+    let cookies = match tab.get_cookies() {
+        Ok(c) => c.into_iter().map(|c| (c.name, c.value)).collect(),
+        Err(e) => {
+            eprintln!("headless_chrome: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = session::save(stub, handle, &cookies) {
+        eprintln!("{}", e);
+    }
+}
+
+/// Quote a (user-controlled) string as a javascript string literal so that it
+/// can be safely interpolated into scripts run in the tab. JSON strings are
+/// valid javascript, except that older engines treat U+2028 and U+2029 as line
+/// terminators, so those are escaped as well.
+fn js_string(s: &str) -> String {
+    // unwrap is fine since serializing a string can't fail
+    serde_json::to_string(s)
+        .unwrap()
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029")
+}
+
 fn attempt_tab_login(
     tab: &Tab,
     url: &str,
@@ -157,10 +380,11 @@ fn attempt_tab_login(
     tab.navigate_to(url)?.wait_until_navigated()?;
     tab.evaluate(
         &format!(
-            "$('#handleOrEmail').val('{}');
-            $('#password').val('{}');
+            "$('#handleOrEmail').val({});
+            $('#password').val({});
             $('input#handleOrEmail').closest('form').submit();",
-            username, password
+            js_string(username),
+            js_string(password)
         ),
         true,
     )?;
@@ -171,15 +395,18 @@ fn attempt_tab_submit(
     tab: &Tab,
     url: &str,
     src: &str,
-    problem_index: &str,
+    problem_field: (&str, String),
     program_type_id: i64,
 ) -> Result<(), failure::Error> {
+    let (field, value) = problem_field;
     tab.navigate_to(url)?.wait_until_navigated()?;
     tab.evaluate(
         &format!(
-            "$('[name=problemIndex],[name=submittedProblemIndex]').val('{}');
-            $('[name=programTypeId]').val('{}');",
-            problem_index, program_type_id
+            "$('[name=problemIndex],[name={}]').val({});
+            $('[name=programTypeId]').val({});",
+            field,
+            js_string(&value),
+            js_string(&program_type_id.to_string())
         ),
         true,
     )?;
@@ -194,7 +421,10 @@ fn attempt_tab_submit(
     #[cfg(feature = "debug-screenshot")]
     let _ = debug_screenshot(tab, "ss_beforesubmit.jpg")?;
 
-    tab.evaluate(&format!("$('[name=source]').val({:?});", src), true)?;
+    tab.evaluate(
+        &format!("$('[name=source]').val({});", js_string(src)),
+        true,
+    )?;
     tab.wait_for_element("input[value=Submit]")?.click()?;
     Ok(())
 }
@@ -207,3 +437,48 @@ fn debug_screenshot(tab: &Tab, filename: &str) -> Result<(), failure::Error> {
     f.write_all(&_jpeg_data)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::js_string;
+
+    /// Parse a string literal produced by [`js_string`] back into a string,
+    /// checking that it can't end the script (or a line) early.
+    fn decode(literal: &str) -> String {
+        assert!(literal.starts_with('"') && literal.ends_with('"'));
+        for c in ['\n', '\r', '\u{2028}', '\u{2029}'] {
+            assert!(!literal.contains(c), "unescaped {:?} in {}", c, literal);
+        }
+        serde_json::from_str(literal).unwrap()
+    }
+
+    #[test]
+    fn js_string_round_trips() {
+        let cases = [
+            "",
+            "hunter2",
+            "it's",
+            "say \"hi\"",
+            "back\\slash\\",
+            "\\\"",
+            "line\nbreak",
+            "crlf\r\nline",
+            "</script><script>alert(1)</script>",
+            "'); alert(1); ('",
+            "пароль ünïcødé 密码 🦀",
+            "line\u{2028}separator\u{2029}paragraph",
+            "#include <bits/stdc++.h>\nint main() {\n\tputs(\"a\\n\");\n}\n",
+            "\u{0}\u{1f}\u{7f}",
+        ];
+        for s in cases.iter() {
+            assert_eq!(decode(&js_string(s)), *s);
+        }
+    }
+
+    #[test]
+    fn js_string_escapes_line_terminators() {
+        assert_eq!(js_string("a\u{2028}b"), "\"a\\u2028b\"");
+        assert_eq!(js_string("a\u{2029}b"), "\"a\\u2029b\"");
+        assert_eq!(js_string("a\r\nb"), "\"a\\r\\nb\"");
+    }
+}
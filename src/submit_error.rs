@@ -0,0 +1,233 @@
+use std::fmt;
+
+/// Reasons a solution couldn't be submitted. Each has its own exit status
+/// (see [`SubmitError::exit_code`]) so that scripts can tell them apart.
+#[derive(Debug)]
+pub enum SubmitError {
+    /// The handle/email or password was rejected.
+    LoginFailed,
+    /// The site asked to log in again whilst submitting.
+    LoginRequired,
+    /// Cloudflare showed a challenge page rather than the requested page.
+    Challenge,
+    /// The same source was submitted to the problem before.
+    Duplicate(String),
+    /// The contest isn't running (not started yet, or submissions closed).
+    ContestNotRunning(String),
+    /// The problem doesn't exist in the contest.
+    WrongProblem(String),
+    /// The language can't be used for the problem.
+    LanguageNotAllowed(String),
+    /// The source is too long (or otherwise doesn't satisfy the site's
+    /// restrictions on sources).
+    SourceTooLong(String),
+    /// Any other error shown on the submit page.
+    Rejected(String),
+    /// Failed to reach the site or to drive the browser.
+    Other(String),
+}
+
+/// Fragments (lowercase) of the errors shown on the submit page of
+/// Codeforces, for each kind of error.
+const DUPLICATE: &[&str] = &["exactly the same code"];
+const CONTEST_NOT_RUNNING: &[&str] = &[
+    "not running",
+    "not started",
+    "contest is over",
+    "contest has finished",
+    "can not submit",
+    "cannot submit",
+];
+const WRONG_PROBLEM: &[&str] =
+    &["valid problem", "no such problem", "problem not found"];
+const LANGUAGE_NOT_ALLOWED: &[&str] = &[
+    "valid language",
+    "language is not allowed",
+    "language is not supported",
+];
+const SOURCE_TOO_LONG: &[&str] = &["too long", "source should satisfy"];
+
+/// Titles of the pages shown by Cloudflare whilst checking the browser.
+const CHALLENGE_TITLES: &[&str] =
+    &["Just a moment...", "Attention Required! | Cloudflare"];
+
+impl SubmitError {
+    /// Classify an error shown on the submit page.
+    pub fn from_message(msg: &str) -> SubmitError {
+        let msg = msg.trim().to_string();
+        let lower = msg.to_lowercase();
+        let has =
+            |fragments: &[&str]| fragments.iter().any(|f| lower.contains(f));
+        if has(DUPLICATE) {
+            SubmitError::Duplicate(msg)
+        } else if has(CONTEST_NOT_RUNNING) {
+            SubmitError::ContestNotRunning(msg)
+        } else if has(WRONG_PROBLEM) {
+            SubmitError::WrongProblem(msg)
+        } else if has(LANGUAGE_NOT_ALLOWED) {
+            SubmitError::LanguageNotAllowed(msg)
+        } else if has(SOURCE_TOO_LONG) {
+            SubmitError::SourceTooLong(msg)
+        } else {
+            SubmitError::Rejected(msg)
+        }
+    }
+
+    /// Name and exit status of the kind of error (None for [`Other`], which
+    /// exits with status 2 like every other command).
+    ///
+    /// [`Other`]: SubmitError::Other
+    fn kind(&self) -> Option<(&'static str, i32)> {
+        Some(match self {
+            SubmitError::LoginFailed => ("login failed", 21),
+            SubmitError::LoginRequired => ("login required", 22),
+            SubmitError::Challenge => ("cloudflare challenge", 23),
+            SubmitError::Duplicate(_) => ("duplicate submission", 24),
+            SubmitError::ContestNotRunning(_) => ("contest not running", 25),
+            SubmitError::WrongProblem(_) => ("wrong problem", 26),
+            SubmitError::LanguageNotAllowed(_) => ("language not allowed", 27),
+            SubmitError::SourceTooLong(_) => ("source too long", 28),
+            SubmitError::Rejected(_) => ("rejected by the submit page", 29),
+            SubmitError::Other(_) => return None,
+        })
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.kind().map_or(2, |(_, code)| code)
+    }
+
+    /// Whether the error is with logging in (or staying logged in) rather
+    /// than with a single solution, so that submitting anything else would
+    /// fail the same way.
+    pub fn is_session_error(&self) -> bool {
+        matches!(
+            self,
+            SubmitError::LoginFailed
+                | SubmitError::LoginRequired
+                | SubmitError::Challenge
+        )
+    }
+}
+
+/// Whether the title of a page is that of a Cloudflare challenge. Other
+/// fragments of challenge pages (eg. their scripts) are also included in
+/// ordinary pages, so aren't checked.
+pub fn is_challenge_title(title: &str) -> bool {
+    CHALLENGE_TITLES.contains(&title.trim())
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubmitError::LoginFailed => {
+                write!(f, "login unsuccessful, incorrect username or password")
+            }
+            SubmitError::LoginRequired => write!(
+                f,
+                "failed to submit problem, the site asked to log in again"
+            ),
+            SubmitError::Challenge => write!(
+                f,
+                "blocked by a Cloudflare challenge, try again later or with \
+                --backend chrome"
+            ),
+            SubmitError::Other(msg) => write!(f, "{}", msg),
+            SubmitError::Duplicate(msg)
+            | SubmitError::ContestNotRunning(msg)
+            | SubmitError::WrongProblem(msg)
+            | SubmitError::LanguageNotAllowed(msg)
+            | SubmitError::SourceTooLong(msg)
+            | SubmitError::Rejected(msg) => write!(
+                f,
+                "failed to submit problem ({}), {}",
+                // unwrap is fine since only Other has no kind
+                self.kind().unwrap().0,
+                msg
+            ),
+        }
+    }
+}
+
+impl From<String> for SubmitError {
+    fn from(msg: String) -> SubmitError {
+        SubmitError::Other(msg)
+    }
+}
+
+impl From<&str> for SubmitError {
+    fn from(msg: &str) -> SubmitError {
+        SubmitError::Other(msg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_messages() {
+        let cases: &[(&str, i32)] = &[
+            ("You have submitted exactly the same code before", 24),
+            ("The contest is not running", 25),
+            ("Contest is over, you can not submit", 25),
+            ("Choose valid problem", 26),
+            ("Choose valid language", 27),
+            ("Source should satisfy regex [^{}]*public\\s+(final)?\\s*class \
+            (\\w+).*", 28),
+            ("Source code is too long", 28),
+            ("Something else went wrong", 29),
+        ];
+        for (msg, code) in cases {
+            assert_eq!(
+                SubmitError::from_message(msg).exit_code(),
+                *code,
+                "{}",
+                msg
+            );
+        }
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let errors = [
+            SubmitError::LoginFailed,
+            SubmitError::LoginRequired,
+            SubmitError::Challenge,
+            SubmitError::Duplicate(String::new()),
+            SubmitError::ContestNotRunning(String::new()),
+            SubmitError::WrongProblem(String::new()),
+            SubmitError::LanguageNotAllowed(String::new()),
+            SubmitError::SourceTooLong(String::new()),
+            SubmitError::Rejected(String::new()),
+        ];
+        let codes: Vec<i32> =
+            errors.iter().map(SubmitError::exit_code).collect();
+        assert_eq!(codes, (21..=29).collect::<Vec<_>>());
+        assert_eq!(SubmitError::Other(String::new()).exit_code(), 2);
+    }
+
+    #[test]
+    fn session_errors() {
+        let codes: Vec<i32> = [
+            SubmitError::LoginFailed,
+            SubmitError::LoginRequired,
+            SubmitError::Challenge,
+            SubmitError::Duplicate(String::new()),
+            SubmitError::Rejected(String::new()),
+            SubmitError::Other(String::new()),
+        ]
+        .iter()
+        .filter(|e| e.is_session_error())
+        .map(SubmitError::exit_code)
+        .collect();
+        assert_eq!(codes, [21, 22, 23]);
+    }
+
+    #[test]
+    fn challenge_titles() {
+        assert!(is_challenge_title("Just a moment..."));
+        assert!(is_challenge_title(" Just a moment...\n"));
+        assert!(!is_challenge_title("Codeforces"));
+        assert!(!is_challenge_title("Blog: Just a moment... please"));
+    }
+}
@@ -0,0 +1,351 @@
+use clap::ArgMatches;
+use reqwest::Url;
+use std::str::FromStr;
+
+/// Where a solution is submitted to. Each kind of contest has its own submit
+/// page (relative to the base url of codeforces.com or a mirror), and its own
+/// page which is shown after submitting successfully.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// A regular contest (`contest/<id>/submit`).
+    Contest(i64),
+    /// A gym contest (`gym/<id>/submit`).
+    Gym(i64),
+    /// A contest in a group (`group/<code>/contest/<id>/submit`).
+    Group(String, i64),
+    /// The problemset (`problemset/submit`), where problems are identified by
+    /// their contest id and index (eg. `1466B`).
+    Problemset(i64),
+}
+
+impl Target {
+    /// Target selected by the `--gym`, `--group` and `--problemset` options
+    /// of `caffeine submit`, defaulting to the kind of contest `id` belongs
+    /// to.
+    pub fn from_args(args: &ArgMatches, id: &ProblemId) -> Target {
+        let contest_id = id.contest_id();
+        if let Some(group) = args.value_of("group") {
+            Target::Group(group.to_string(), contest_id)
+        } else if args.is_present("gym") {
+            Target::Gym(contest_id)
+        } else if args.is_present("problemset") {
+            Target::Problemset(contest_id)
+        } else {
+            id.target.clone()
+        }
+    }
+
+    pub fn contest_id(&self) -> i64 {
+        match self {
+            Target::Contest(i)
+            | Target::Gym(i)
+            | Target::Group(_, i)
+            | Target::Problemset(i) => *i,
+        }
+    }
+
+    /// Path of the contest (or problemset) the target belongs to.
+    fn prefix(&self) -> String {
+        match self {
+            Target::Contest(i) => format!("contest/{}", i),
+            Target::Gym(i) => format!("gym/{}", i),
+            Target::Group(g, i) => format!("group/{}/contest/{}", g, i),
+            Target::Problemset(_) => "problemset".to_string(),
+        }
+    }
+
+    pub fn submit_url(&self, base_url: &str) -> String {
+        format!("{}{}/submit", base_url, self.prefix())
+    }
+
+    /// Url of the page shown after submitting successfully (the user's own
+    /// submissions).
+    pub fn success_url(&self, base_url: &str) -> String {
+        match self {
+            Target::Problemset(_) => {
+                format!("{}{}/status?my=on", base_url, self.prefix())
+            }
+            _ => format!("{}{}/my", base_url, self.prefix()),
+        }
+    }
+
+    /// Whether `url` is the page shown after submitting successfully (query
+    /// parameters are ignored).
+    pub fn is_success_url(&self, base_url: &str, url: &Url) -> bool {
+        let path = |u: &Url| u.path().trim_end_matches('/').to_string();
+        Url::parse(&self.success_url(base_url))
+            .map(|expected| path(&expected) == path(url))
+            .unwrap_or(false)
+    }
+
+    /// Url of the statement of a problem (which has its sample testcases).
+    pub fn problem_url(&self, base_url: &str, problem_index: &str) -> String {
+        match self {
+            Target::Problemset(i) => format!(
+                "{}problemset/problem/{}/{}",
+                base_url, i, problem_index
+            ),
+            _ => {
+                format!(
+                    "{}{}/problem/{}",
+                    base_url,
+                    self.prefix(),
+                    problem_index
+                )
+            }
+        }
+    }
+
+    /// Name and value of the submit form field selecting the problem.
+    pub fn problem_field(&self, problem_index: &str) -> (&'static str, String) {
+        match self {
+            Target::Problemset(i) => {
+                ("submittedProblemCode", format!("{}{}", i, problem_index))
+            }
+            _ => ("submittedProblemIndex", problem_index.to_string()),
+        }
+    }
+}
+
+/// A contest or problem given on the command line, either as a contest id
+/// (eg. `1466`), a problem code (eg. `1466B`) or the url of a contest or
+/// problem on codeforces.com or one of its mirrors (eg.
+/// `https://codeforces.com/contest/1466/problem/B`,
+/// `https://codeforces.com/problemset/problem/1466/B`,
+/// `https://codeforces.com/gym/102992/problem/A` or
+/// `https://codeforces.com/group/MWSDmqGsZm/contest/223339/problem/A`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProblemId {
+    pub target: Target,
+    /// Problem index (eg. `B`), if a problem rather than a contest was given.
+    pub index: Option<String>,
+}
+
+impl ProblemId {
+    pub fn contest_id(&self) -> i64 {
+        self.target.contest_id()
+    }
+}
+
+impl FromStr for ProblemId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let id = if s.contains('/') {
+            parse_url(s)
+        } else {
+            parse_code(s)
+        };
+        id.ok_or_else(|| {
+            format!(
+                "invalid contest/problem `{}` (expected eg. 1466, 1466B or a \
+                problem url)",
+                s
+            )
+        })
+    }
+}
+
+/// Problem indices are a letter optionally followed by a number (eg. `F2`).
+pub fn parse_index(s: &str) -> Option<String> {
+    let mut chars = s.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_digit());
+    if valid {
+        Some(s.to_ascii_uppercase())
+    } else {
+        None
+    }
+}
+
+fn parse_code(s: &str) -> Option<ProblemId> {
+    let n = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let contest_id = s[..n].parse().ok()?;
+    let index = match &s[n..] {
+        "" => None,
+        i => Some(parse_index(i)?),
+    };
+    Some(ProblemId {
+        target: Target::Contest(contest_id),
+        index,
+    })
+}
+
+fn parse_url(s: &str) -> Option<ProblemId> {
+    // the scheme is optional (eg. codeforces.com/contest/1466)
+    let url = Url::parse(s)
+        .ok()
+        .filter(|u| u.has_host())
+        .or_else(|| Url::parse(&format!("https://{}", s)).ok())?;
+    let segments: Vec<&str> =
+        url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let (target, rest) = match segments.as_slice() {
+        ["contest", i, rest @ ..] => (Target::Contest(i.parse().ok()?), rest),
+        ["gym", i, rest @ ..] => (Target::Gym(i.parse().ok()?), rest),
+        ["group", g, "contest", i, rest @ ..] => {
+            (Target::Group(g.to_string(), i.parse().ok()?), rest)
+        }
+        ["problemset", "problem", i, rest @ ..] => {
+            (Target::Problemset(i.parse().ok()?), rest)
+        }
+        _ => return None,
+    };
+    let index = match (&target, rest) {
+        // problemset urls are only for problems
+        (Target::Problemset(_), [i, ..]) => Some(parse_index(i)?),
+        (Target::Problemset(_), []) => return None,
+        (_, ["problem", i, ..]) => Some(parse_index(i)?),
+        _ => None,
+    };
+    Some(ProblemId { target, index })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(target: Target, index: Option<&str>) -> ProblemId {
+        ProblemId {
+            target,
+            index: index.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn parses_codes() {
+        for (s, expected) in [
+            ("1466", id(Target::Contest(1466), None)),
+            ("1466B", id(Target::Contest(1466), Some("B"))),
+            ("1466b", id(Target::Contest(1466), Some("B"))),
+            ("1466F2", id(Target::Contest(1466), Some("F2"))),
+            (" 1466c1 ", id(Target::Contest(1466), Some("C1"))),
+        ] {
+            assert_eq!(s.parse::<ProblemId>(), Ok(expected), "{}", s);
+        }
+    }
+
+    #[test]
+    fn parses_urls() {
+        let gym = Target::Gym(102992);
+        let group = Target::Group("MWSDmqGsZm".into(), 223339);
+        for (s, expected) in [
+            (
+                "https://codeforces.com/contest/1466",
+                id(Target::Contest(1466), None),
+            ),
+            (
+                "https://codeforces.com/contest/1466/",
+                id(Target::Contest(1466), None),
+            ),
+            (
+                "https://codeforces.com/contest/1466/problem/B",
+                id(Target::Contest(1466), Some("B")),
+            ),
+            (
+                "codeforces.com/contest/1466/problem/b",
+                id(Target::Contest(1466), Some("B")),
+            ),
+            (
+                "https://m1.codeforces.com/contest/1466/problem/F2?locale=en",
+                id(Target::Contest(1466), Some("F2")),
+            ),
+            (
+                "https://codeforces.com/contest/1466/submit",
+                id(Target::Contest(1466), None),
+            ),
+            (
+                "https://codeforces.com/problemset/problem/1466/B",
+                id(Target::Problemset(1466), Some("B")),
+            ),
+            (
+                "codeforces.com/problemset/problem/1466/B",
+                id(Target::Problemset(1466), Some("B")),
+            ),
+            ("https://codeforces.com/gym/102992", id(gym.clone(), None)),
+            (
+                "https://codeforces.com/gym/102992/problem/A",
+                id(gym.clone(), Some("A")),
+            ),
+            ("codeforces.com/gym/102992/problem/A", id(gym, Some("A"))),
+            (
+                "https://codeforces.com/group/MWSDmqGsZm/contest/223339",
+                id(group.clone(), None),
+            ),
+            (
+                "https://codeforces.com/group/MWSDmqGsZm/contest/223339/problem/A",
+                id(group.clone(), Some("A")),
+            ),
+            (
+                "codeforces.com/group/MWSDmqGsZm/contest/223339/problem/A",
+                id(group, Some("A")),
+            ),
+        ] {
+            assert_eq!(s.parse::<ProblemId>(), Ok(expected), "{}", s);
+        }
+    }
+
+    #[test]
+    fn invalid_ids() {
+        for s in [
+            "",
+            "B",
+            "1466BB",
+            "1466-B",
+            "https://codeforces.com/",
+            "https://codeforces.com/contest/x",
+            "https://codeforces.com/contest/1466/problem/1",
+            "https://codeforces.com/problemset/problem/1466",
+            "https://codeforces.com/problemset/problem/B/1466",
+            "https://codeforces.com/group/MWSDmqGsZm/problem/A",
+            "https://codeforces.com/blog/entry/1466",
+        ] {
+            assert!(s.parse::<ProblemId>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn urls() {
+        let base = "https://codeforces.com/";
+        for (target, submit, success, problem) in [
+            (
+                Target::Contest(1466),
+                "contest/1466/submit",
+                "contest/1466/my",
+                "contest/1466/problem/B",
+            ),
+            (
+                Target::Gym(102992),
+                "gym/102992/submit",
+                "gym/102992/my",
+                "gym/102992/problem/B",
+            ),
+            (
+                Target::Group("g".into(), 1),
+                "group/g/contest/1/submit",
+                "group/g/contest/1/my",
+                "group/g/contest/1/problem/B",
+            ),
+            (
+                Target::Problemset(1466),
+                "problemset/submit",
+                "problemset/status?my=on",
+                "problemset/problem/1466/B",
+            ),
+        ] {
+            assert_eq!(target.submit_url(base), base.to_string() + submit);
+            assert_eq!(target.success_url(base), base.to_string() + success);
+            assert_eq!(
+                target.problem_url(base, "B"),
+                base.to_string() + problem
+            );
+            let url = Url::parse(&(base.to_string() + success)).unwrap();
+            assert!(target.is_success_url(base, &url));
+        }
+        let url =
+            Url::parse("https://codeforces.com/contest/1466/my/").unwrap();
+        assert!(Target::Contest(1466).is_success_url(base, &url));
+        assert!(!Target::Contest(1467).is_success_url(base, &url));
+        assert!(!Target::Gym(1466).is_success_url(base, &url));
+    }
+}
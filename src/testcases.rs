@@ -0,0 +1,223 @@
+use codeforces_api::responses::CFProblem;
+use codeforces_api::Error as ApiError;
+use select::document::Document;
+use select::node::Node;
+use select::predicate::{Class, Descendant, Name};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::target::Target;
+
+/// A sample testcase scraped from a problem page: an input along with the
+/// output expected for it.
+#[derive(Serialize, Clone)]
+pub struct Testcase {
+    pub input: String,
+    pub output: String,
+}
+
+/// Fetch the sample testcases of a problem by scraping its problem page on
+/// `base_url`. The problem is looked up in `target` (eg. a gym contest) if
+/// given, else in the problemset. The inputs are also stored in
+/// `problem.input_testcases`.
+pub fn fetch_for_problem(
+    problem: &mut CFProblem,
+    target: Option<&Target>,
+    base_url: &str,
+) -> Result<Vec<Testcase>, ApiError> {
+    let url = match (target, problem.contest_id, &problem.index) {
+        (Some(t), _, Some(i)) => t.problem_url(base_url, i),
+        (None, Some(c), Some(i)) => {
+            Target::Problemset(c).problem_url(base_url, i)
+        }
+        _ => {
+            return Err(ApiError::Testcases(
+                "problem.contest_id and problem.index fields are required.",
+            ))
+        }
+    };
+    let html = reqwest::blocking::get(url)
+        .and_then(|r| r.text())
+        .map_err(ApiError::Http)?;
+    let testcases = parse_testcases(&html)?;
+    problem.input_testcases =
+        Some(testcases.iter().map(|t| t.input.clone()).collect());
+    Ok(testcases)
+}
+
+/// Pair every sample input on a problem page with its expected output.
+fn parse_testcases(html: &str) -> Result<Vec<Testcase>, ApiError> {
+    let document = Document::from(html);
+    let inputs: Vec<String> = document
+        .find(Descendant(Class("input"), Name("pre")))
+        .map(pre_text)
+        .collect();
+    let outputs: Vec<String> = document
+        .find(Descendant(Class("output"), Name("pre")))
+        .map(pre_text)
+        .collect();
+    if inputs.is_empty() {
+        Err(ApiError::Testcases(
+            "No testcase input found for this problem.",
+        ))
+    } else if inputs.len() != outputs.len() {
+        Err(ApiError::Testcases(
+            "Number of testcase inputs and outputs do not match for this \
+            problem.",
+        ))
+    } else {
+        Ok(inputs
+            .into_iter()
+            .zip(outputs)
+            .map(|(input, output)| Testcase { input, output })
+            .collect())
+    }
+}
+
+/// Text content of a `<pre>` block. Older problems separate lines with `<br>`
+/// whilst newer ones wrap every line in its own `<div>`.
+fn pre_text(pre: Node) -> String {
+    fn walk(node: Node, s: &mut String) {
+        for child in node.children() {
+            if let Some(t) = child.as_text() {
+                s.push_str(t);
+            } else if child.name() == Some("br") {
+                s.push('\n');
+            } else {
+                walk(child, s);
+                if child.name() == Some("div") && !s.ends_with('\n') {
+                    s.push('\n');
+                }
+            }
+        }
+    }
+    let mut s = String::new();
+    walk(pre, &mut s);
+    s.trim_matches('\n').to_string()
+}
+
+/// Add the fetched testcases to each problem of a serialized result (eg.
+/// contest standings or a problemset) as a `testcases` list.
+pub fn attach(v: &mut Value, testcases: &[Option<Vec<Testcase>>]) {
+    if let Some(problems) = v.get_mut("problems").and_then(|p| p.as_array_mut())
+    {
+        for (p, t) in problems.iter_mut().zip(testcases) {
+            if let (Some(p), Some(t)) = (p.as_object_mut(), t) {
+                // unwrap is probably ok here since serializing errors are
+                // very rare
+                p.insert("testcases".into(), serde_json::to_value(t).unwrap());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Sample tests of an older problem, with lines separated by `<br>`.
+    const BR_LAYOUT: &str = r#"<div class="sample-test">
+<div class="input"><div class="title">Input</div><pre>3<br />1 2 3<br /></pre></div>
+<div class="output"><div class="title">Output</div><pre>6<br /></pre></div>
+<div class="input"><div class="title">Input</div><pre>1<br/>&lt;5&gt;</pre></div>
+<div class="output"><div class="title">Output</div><pre>YES<br>NO</pre></div>
+</div>"#;
+
+    /// Sample tests of a newer problem, with every line in its own `<div>`.
+    const DIV_LAYOUT: &str = r#"<div class="sample-test">
+<div class="input"><div class="title">Input</div><pre>
+<div class="test-example-line test-example-line-even test-example-line-0">2</div><div class="test-example-line test-example-line-odd test-example-line-1">1 2</div><div class="test-example-line test-example-line-even test-example-line-2">&amp;3 4</div></pre></div>
+<div class="output"><div class="title">Output</div><pre>
+3
+7
+</pre></div>
+</div>"#;
+
+    fn pairs(testcases: Vec<Testcase>) -> Vec<(String, String)> {
+        testcases.into_iter().map(|t| (t.input, t.output)).collect()
+    }
+
+    fn pair(input: &str, output: &str) -> (String, String) {
+        (input.to_string(), output.to_string())
+    }
+
+    #[test]
+    fn parses_br_layout() {
+        assert_eq!(
+            pairs(parse_testcases(BR_LAYOUT).unwrap()),
+            [pair("3\n1 2 3", "6"), pair("1\n<5>", "YES\nNO")]
+        );
+    }
+
+    #[test]
+    fn parses_div_layout() {
+        assert_eq!(
+            pairs(parse_testcases(DIV_LAYOUT).unwrap()),
+            [pair("2\n1 2\n&3 4", "3\n7")]
+        );
+    }
+
+    #[test]
+    fn missing_or_unmatched_samples() {
+        assert!(parse_testcases("<p>no samples</p>").is_err());
+        let unmatched =
+            BR_LAYOUT.replacen("class=\"output\"", "class=\"x\"", 1);
+        assert!(parse_testcases(&unmatched).is_err());
+    }
+
+    /// Serve `BR_LAYOUT` once, returning the base url and the path requested.
+    fn serve_once() -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\
+                Connection: close\r\n\r\n{}",
+                BR_LAYOUT.len(),
+                BR_LAYOUT
+            )
+            .unwrap();
+            line.split(' ').nth(1).unwrap().to_string()
+        });
+        (base_url, handle)
+    }
+
+    #[test]
+    fn fetches_from_target() {
+        for (target, path) in [
+            (Some(Target::Contest(1466)), "/contest/1466/problem/B"),
+            (Some(Target::Gym(102992)), "/gym/102992/problem/B"),
+            (
+                Some(Target::Group("MWSDmqGsZm".into(), 223339)),
+                "/group/MWSDmqGsZm/contest/223339/problem/B",
+            ),
+            (None, "/problemset/problem/1466/B"),
+        ] {
+            let (base_url, server) = serve_once();
+            let mut problem: CFProblem =
+                serde_json::from_value(serde_json::json!({
+                    "contestId": 1466,
+                    "index": "B",
+                    "name": "Last minute enhancements",
+                    "type": "PROGRAMMING",
+                    "tags": [],
+                }))
+                .unwrap();
+            let testcases =
+                fetch_for_problem(&mut problem, target.as_ref(), &base_url)
+                    .unwrap();
+            assert_eq!(server.join().unwrap(), path);
+            assert_eq!(testcases.len(), 2);
+            assert_eq!(
+                problem.input_testcases.unwrap(),
+                ["3\n1 2 3", "1\n<5>"]
+            );
+        }
+    }
+}
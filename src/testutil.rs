@@ -0,0 +1,75 @@
+//! Helpers shared by the unit tests.
+
+use directories::ProjectDirs;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, Once};
+
+static INIT: Once = Once::new();
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn root() -> PathBuf {
+    std::env::temp_dir().join(format!("caffeine-test-{}", std::process::id()))
+}
+
+/// Point the config, data and cache dirs at an empty temporary directory so
+/// that tests never touch the user's files. The returned guard has to be held
+/// for the rest of the test, since tests using the dirs can't run at the same
+/// time.
+pub fn isolated_dirs() -> MutexGuard<'static, ()> {
+    INIT.call_once(|| {
+        for (var, dir) in [
+            ("XDG_CONFIG_HOME", "config"),
+            ("XDG_DATA_HOME", "data"),
+            ("XDG_CACHE_HOME", "cache"),
+        ] {
+            std::env::set_var(var, root().join(dir));
+        }
+    });
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = fs::remove_dir_all(root());
+    guard
+}
+
+pub fn proj_dirs() -> ProjectDirs {
+    ProjectDirs::from(crate::NAME_QUL, crate::NAME_ORG, crate::NAME_BIN)
+        .unwrap()
+}
+
+/// Replace config.yml (within [`isolated_dirs`]).
+pub fn write_config(yaml: &str) {
+    let dir = proj_dirs().config_dir().to_path_buf();
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(crate::CONF_FILE_NAME), yaml).unwrap();
+}
+
+/// Write an executable shell script (within [`isolated_dirs`]).
+#[cfg(unix)]
+pub fn write_script(name: &str, script: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = root().join("bin");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, format!("#!/bin/sh\n{}", script)).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+/// Stand-in for `secret-tool` which keeps each secret in a file named after
+/// its account (next to the script).
+#[cfg(unix)]
+pub fn secret_tool_stub() -> PathBuf {
+    write_script(
+        "secret-tool",
+        r#"cmd=$1; shift
+[ "$cmd" = store ] && [ "$1" = --label ] && shift 2
+[ "$1 $2 $3" = "service caffeine account" ] || { echo "bad args" >&2; exit 2; }
+f="$(dirname "$0")/secret-$(printf %s "$4" | tr ':/' '__')"
+case $cmd in
+    store) cat > "$f" ;;
+    lookup) [ -f "$f" ] || exit 1; cat "$f" ;;
+    *) echo "unsupported command $cmd" >&2; exit 2 ;;
+esac
+"#,
+    )
+}
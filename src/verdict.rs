@@ -0,0 +1,390 @@
+use clap::ArgMatches;
+use codeforces_api::requests::CFContestCommand;
+use codeforces_api::responses::{CFResult, CFSubmission, CFSubmissionVerdict};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::handlers::{exit_with_error, get_optional_arg_of_type};
+use crate::{api, config};
+
+/// Exit codes used by `caffeine submit --wait` for each final verdict.
+/// Verdicts which aren't listed exit with [`OTHER_VERDICT_EXIT_CODE`].
+pub const EXIT_CODES: &[(&str, i32)] = &[
+    ("OK", 0),
+    ("WRONG_ANSWER", 11),
+    ("TIME_LIMIT_EXCEEDED", 12),
+    ("MEMORY_LIMIT_EXCEEDED", 13),
+    ("RUNTIME_ERROR", 14),
+    ("COMPILATION_ERROR", 15),
+];
+pub const OTHER_VERDICT_EXIT_CODE: i32 = 19;
+/// Exit code used when a submission is still in queue or being tested once
+/// the judging timeout has passed.
+pub const JUDGING_TIMEOUT_EXIT_CODE: i32 = 18;
+
+/// Number of recent submissions fetched whilst polling.
+const POLL_COUNT: i64 = 10;
+/// How long to wait for a new submission to show up in `contest.status`.
+const APPEAR_TIMEOUT: Duration = Duration::from_secs(120);
+/// Default number of seconds to wait for a submission to be judged.
+const DEFAULT_JUDGING_TIMEOUT: f64 = 600.0;
+const POLL_DELAY: Duration = Duration::from_secs(1);
+
+/// Settings which control how long to wait for a verdict.
+pub struct WaitSettings {
+    /// How long to wait for the submission to show up at all.
+    pub appear_timeout: Duration,
+    /// How long to wait (overall) for the submission to be judged.
+    pub judging_timeout: Duration,
+}
+
+impl WaitSettings {
+    /// Read the judging timeout from the command line, falling back to
+    /// config.yml and then to the default.
+    pub fn from_args(args: &ArgMatches) -> WaitSettings {
+        let conf = config::get_config_or_default();
+        let judging_timeout = get_optional_arg_of_type(args, "judgingtimeout")
+            .or(conf.judging_timeout)
+            .unwrap_or(DEFAULT_JUDGING_TIMEOUT);
+        if !judging_timeout.is_finite() || judging_timeout < 0.0 {
+            exit_with_error("judging timeout must be a non-negative number");
+        }
+        WaitSettings {
+            appear_timeout: APPEAR_TIMEOUT,
+            judging_timeout: Duration::from_secs_f64(judging_timeout),
+        }
+    }
+}
+
+/// Reasons [`wait_for_verdict`] gave up on a submission.
+#[derive(Debug)]
+pub enum WaitError {
+    /// No new submission showed up in `contest.status`.
+    NotFound,
+    /// The submission (with the given id) was still in queue or being tested,
+    /// having passed the given number of tests so far.
+    NotJudged(i64, i64),
+}
+
+impl WaitError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            WaitError::NotFound => 2,
+            WaitError::NotJudged(..) => JUDGING_TIMEOUT_EXIT_CODE,
+        }
+    }
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WaitError::NotFound => {
+                write!(f, "submission didn't appear in contest status")
+            }
+            WaitError::NotJudged(id, passed) => write!(
+                f,
+                "submission {} still wasn't judged (passed {} tests), \
+                giving up",
+                id, passed
+            ),
+        }
+    }
+}
+
+/// Name of a verdict as given by the API (eg. `WRONG_ANSWER`).
+pub fn verdict_name(v: &CFSubmissionVerdict) -> String {
+    // unwrap is probably ok here since serializing errors are very rare
+    serde_json::to_value(v)
+        .unwrap()
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
+
+pub fn exit_code(v: &CFSubmissionVerdict) -> i32 {
+    let name = verdict_name(v);
+    EXIT_CODES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map_or(OTHER_VERDICT_EXIT_CODE, |(_, c)| *c)
+}
+
+fn status_command(contest_id: i64, handle: &str) -> CFContestCommand {
+    CFContestCommand::Status {
+        contest_id,
+        handle: Some(handle.to_string()),
+        from: Some(1),
+        count: Some(POLL_COUNT),
+    }
+}
+
+fn recent_submissions(
+    args: &ArgMatches,
+    contest_id: i64,
+    handle: &str,
+) -> Vec<CFSubmission> {
+    let x = status_command(contest_id, handle);
+    match api::request(args, &x) {
+        Ok(CFResult::CFSubmissionVec(v)) => v,
+        Ok(_) => exit_with_error(
+            "Incorrectly parsed contest status response object.",
+        ),
+        Err(e) => exit_with_error(e),
+    }
+}
+
+/// Id of the most recent submission by `handle` to a contest (used to tell
+/// which submission is new after submitting).
+pub fn latest_submission_id(
+    args: &ArgMatches,
+    contest_id: i64,
+    handle: &str,
+) -> Option<i64> {
+    recent_submissions(args, contest_id, handle)
+        .iter()
+        .map(|s| s.id)
+        .max()
+}
+
+/// Poll `contest.status` until the first submission by `handle` to `problem`
+/// newer than `after` has been judged, reporting testing progress on stderr.
+/// Gives up if the submission doesn't show up, or isn't judged within the
+/// judging timeout.
+pub fn wait_for_verdict(
+    args: &ArgMatches,
+    contest_id: i64,
+    problem: &str,
+    handle: &str,
+    after: Option<i64>,
+    settings: &WaitSettings,
+) -> Result<CFSubmission, WaitError> {
+    let start = Instant::now();
+    let mut last_progress = None;
+    loop {
+        let submission = recent_submissions(args, contest_id, handle)
+            .into_iter()
+            .filter(|s| after.is_none_or(|a| s.id > a))
+            .filter(|s| {
+                s.problem
+                    .index
+                    .as_ref()
+                    .is_some_and(|i| i.eq_ignore_ascii_case(problem))
+            })
+            .min_by_key(|s| s.id);
+        match submission {
+            Some(s) => match s.verdict {
+                Some(CFSubmissionVerdict::Testing) | None => {
+                    if start.elapsed() >= settings.judging_timeout {
+                        return Err(WaitError::NotJudged(
+                            s.id,
+                            s.passed_test_count,
+                        ));
+                    }
+                    if last_progress != Some(s.passed_test_count) {
+                        eprintln!(
+                            "submission {}: testing (passed {} tests)",
+                            s.id, s.passed_test_count
+                        );
+                        last_progress = Some(s.passed_test_count);
+                    }
+                }
+                Some(_) => return Ok(s),
+            },
+            None if start.elapsed() >= settings.appear_timeout => {
+                return Err(WaitError::NotFound)
+            }
+            None => {}
+        }
+        std::thread::sleep(POLL_DELAY);
+    }
+}
+
+/// Final verdict of a submission along with its resource usage.
+pub fn summary(s: &CFSubmission) -> String {
+    // unwrap is fine since only judged submissions are summarized
+    let v = s.verdict.as_ref().unwrap();
+    format!(
+        "{} (passed {} tests, {} ms, {} KB)",
+        verdict_name(v),
+        s.passed_test_count,
+        s.time_consumed_millis,
+        s.memory_consumed_bytes / 1024
+    )
+}
+
+/// Print the final verdict of a submission, returning its exit code.
+pub fn report(s: &CFSubmission) -> i32 {
+    println!("{}", summary(s));
+    // unwrap is fine since only judged submissions are reported
+    exit_code(s.verdict.as_ref().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cache, handlers, testutil};
+    use clap::{App, Arg};
+    use codeforces_api::requests::CFAPIRequestable;
+    use serde_json::json;
+
+    fn submission(
+        id: i64,
+        index: &str,
+        verdict: Option<&str>,
+        passed: i64,
+    ) -> serde_json::Value {
+        json!({
+            "id": id,
+            "contestId": 1,
+            "creationTimeSeconds": 1600000000 + id,
+            "relativeTimeSeconds": 2147483647,
+            "problem": {
+                "contestId": 1,
+                "index": index,
+                "name": "Theatre Square",
+                "type": "PROGRAMMING",
+                "tags": [],
+            },
+            "author": {
+                "contestId": 1,
+                "members": [{"handle": "tourist"}],
+                "participantType": "PRACTICE",
+                "ghost": false,
+            },
+            "programmingLanguage": "GNU C++17",
+            "verdict": verdict,
+            "testset": "TESTS",
+            "passedTestCount": passed,
+            "timeConsumedMillis": 46,
+            "memoryConsumedBytes": 4096000,
+        })
+    }
+
+    fn parse(v: serde_json::Value) -> CFSubmission {
+        serde_json::from_value(v).unwrap()
+    }
+
+    fn args(extra: &[&str]) -> ArgMatches<'static> {
+        let app = App::new("caffeine").args(&[
+            Arg::with_name("offline").long("offline"),
+            Arg::with_name("key").long("key").takes_value(true),
+            Arg::with_name("secret").long("secret").takes_value(true),
+            Arg::with_name("judgingtimeout")
+                .long("judging-timeout")
+                .takes_value(true),
+        ]);
+        let mut argv = vec!["caffeine", "--offline", "--key", "k"];
+        argv.extend(["--secret", "s"]);
+        argv.extend(extra);
+        app.get_matches_from(argv)
+    }
+
+    /// Stand in for the API by caching a `contest.status` response, which is
+    /// then served in offline mode.
+    fn serve_status(args: &ArgMatches, submissions: Vec<serde_json::Value>) {
+        let x = status_command(1, "tourist");
+        let (key, _) = handlers::get_api_key_secret(args);
+        let raw = json!({"status": "OK", "result": submissions}).to_string();
+        cache::put(x.method_name(), &x.query_params(), &key, &raw);
+    }
+
+    fn settings(appear: u64, judging: u64) -> WaitSettings {
+        WaitSettings {
+            appear_timeout: Duration::from_secs(appear),
+            judging_timeout: Duration::from_secs(judging),
+        }
+    }
+
+    #[test]
+    fn maps_verdicts_to_exit_codes() {
+        for (verdict, code) in [
+            ("OK", 0),
+            ("WRONG_ANSWER", 11),
+            ("TIME_LIMIT_EXCEEDED", 12),
+            ("MEMORY_LIMIT_EXCEEDED", 13),
+            ("RUNTIME_ERROR", 14),
+            ("COMPILATION_ERROR", 15),
+            ("SKIPPED", 19),
+            ("CHALLENGED", 19),
+            ("IDLENESS_LIMIT_EXCEEDED", 19),
+        ] {
+            let v: CFSubmissionVerdict =
+                serde_json::from_value(json!(verdict)).unwrap();
+            assert_eq!(verdict_name(&v), verdict);
+            assert_eq!(exit_code(&v), code, "{}", verdict);
+        }
+        let codes: Vec<i32> = EXIT_CODES.iter().map(|(_, c)| *c).collect();
+        assert!(!codes.contains(&JUDGING_TIMEOUT_EXIT_CODE));
+        assert_ne!(JUDGING_TIMEOUT_EXIT_CODE, OTHER_VERDICT_EXIT_CODE);
+    }
+
+    #[test]
+    fn reports_verdicts() {
+        for (verdict, code, expected) in [
+            ("OK", 0, "OK (passed 12 tests, 46 ms, 4000 KB)"),
+            (
+                "WRONG_ANSWER",
+                11,
+                "WRONG_ANSWER (passed 12 tests, 46 ms, 4000 KB)",
+            ),
+            ("SKIPPED", 19, "SKIPPED (passed 12 tests, 46 ms, 4000 KB)"),
+        ] {
+            let s = parse(submission(6, "A", Some(verdict), 12));
+            assert_eq!(summary(&s), expected);
+            assert_eq!(report(&s), code, "{}", verdict);
+        }
+    }
+
+    #[test]
+    fn reads_judging_timeout() {
+        let _dirs = testutil::isolated_dirs();
+        let timeout = |extra| WaitSettings::from_args(&args(extra));
+        assert_eq!(timeout(&[]).judging_timeout, Duration::from_secs(600));
+        testutil::write_config("judging_timeout: 30\n");
+        assert_eq!(timeout(&[]).judging_timeout, Duration::from_secs(30));
+        let t = timeout(&["--judging-timeout", "1.5"]).judging_timeout;
+        assert_eq!(t, Duration::from_millis(1500));
+        assert_eq!(timeout(&[]).appear_timeout, APPEAR_TIMEOUT);
+    }
+
+    #[test]
+    fn waits_for_verdict() {
+        let _dirs = testutil::isolated_dirs();
+        let args = args(&[]);
+        let wait = |after, settings: &WaitSettings| {
+            wait_for_verdict(&args, 1, "a", "tourist", after, settings)
+        };
+
+        // the new submission is the oldest one to the problem after `after`
+        serve_status(
+            &args,
+            vec![
+                submission(8, "A", Some("OK"), 20),
+                submission(7, "B", None, 0),
+                submission(6, "A", Some("WRONG_ANSWER"), 3),
+                submission(5, "A", Some("OK"), 20),
+            ],
+        );
+        let s = wait(Some(5), &settings(0, 0)).unwrap();
+        assert_eq!((s.id, report(&s)), (6, 11));
+
+        // the submission never shows up
+        let e = wait(Some(8), &settings(0, 60)).unwrap_err();
+        assert!(matches!(e, WaitError::NotFound), "{:?}", e);
+        assert_eq!(e.exit_code(), 2);
+
+        // the submission is still in queue or being tested
+        for verdict in [None, Some("TESTING")] {
+            serve_status(
+                &args,
+                vec![
+                    submission(6, "A", verdict, 3),
+                    submission(5, "A", Some("OK"), 20),
+                ],
+            );
+            let e = wait(Some(5), &settings(60, 0)).unwrap_err();
+            assert!(matches!(e, WaitError::NotJudged(6, 3)), "{:?}", e);
+            assert_eq!(e.exit_code(), JUDGING_TIMEOUT_EXIT_CODE);
+        }
+    }
+}
@@ -0,0 +1,452 @@
+use codeforces_api::responses::CFProblem;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, DirBuilder};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::target::{self, ProblemId};
+use crate::testcases::Testcase;
+
+const DEFAULT_PROBLEM_DIR: &str = "<problem>";
+const DEFAULT_SOLUTION_FILENAME: &str = "<problem>.cpp";
+const DEFAULT_INPUT_FILENAME: &str = "in<num>.txt";
+const DEFAULT_OUTPUT_FILENAME: &str = "out<num>.txt";
+const DEFAULT_CHECKER_FILENAME: &str = "checker.txt";
+
+/// Filename patterns (and template) used to lay out a contest workspace.
+///
+/// Patterns may contain the following placeholders:
+/// - `<contestid>`: contestId of the contest (eg. 1466).
+/// - `<problem>`: lowercase problem index (eg. b or c1).
+/// - `<PROBLEM>`: problem index as given by Codeforces (eg. B or C1).
+/// - `<name>`: full name of the problem (with path separators replaced and
+///   leading dots removed in filenames).
+/// - `<num>`: 1-based index of the testcase (testcase filenames only).
+pub struct WorkspaceSettings {
+    pub problem_dir: String,
+    pub solution_filename: String,
+    pub input_filename: String,
+    pub output_filename: String,
+    pub checker_filename: String,
+    pub template: Option<String>,
+}
+
+impl WorkspaceSettings {
+    pub fn from_config(conf: &Config) -> WorkspaceSettings {
+        let w = conf.workspace.clone().unwrap_or_default();
+        WorkspaceSettings {
+            problem_dir: w
+                .problem_dir
+                .unwrap_or_else(|| DEFAULT_PROBLEM_DIR.to_string()),
+            solution_filename: w
+                .solution_filename
+                .unwrap_or_else(|| DEFAULT_SOLUTION_FILENAME.to_string()),
+            input_filename: w
+                .input_filename
+                .unwrap_or_else(|| DEFAULT_INPUT_FILENAME.to_string()),
+            output_filename: w
+                .output_filename
+                .unwrap_or_else(|| DEFAULT_OUTPUT_FILENAME.to_string()),
+            checker_filename: w
+                .checker_filename
+                .unwrap_or_else(|| DEFAULT_CHECKER_FILENAME.to_string()),
+            template: w.template,
+        }
+    }
+
+    /// Read the solution template (if one is set), expanding a leading `~`.
+    pub fn read_template(&self) -> Result<Option<String>, String> {
+        match self.template {
+            Some(ref t) => match fs::read_to_string(expand_home(t)) {
+                Ok(s) => Ok(Some(s)),
+                Err(e) => {
+                    Err(format!("unable to read template file {}: {}", t, e))
+                }
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), directories::BaseDirs::new()) {
+        (Some(rest), Some(dirs)) => dirs.home_dir().join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Replace every placeholder in `pattern` with its value.
+pub fn render(pattern: &str, vars: &[(&str, &str)]) -> String {
+    vars.iter().fold(pattern.to_string(), |acc, (k, v)| {
+        acc.replace(&format!("<{}>", k), v)
+    })
+}
+
+fn problem_vars(contest_id: i64, problem: &CFProblem) -> Vec<(&str, String)> {
+    let index = problem.index.clone().unwrap_or_default();
+    vec![
+        ("contestid", contest_id.to_string()),
+        ("problem", index.to_lowercase()),
+        ("PROBLEM", index),
+        ("name", problem.name.clone()),
+    ]
+}
+
+/// A problem name which is safe to use in a path: path separators (and
+/// control characters) are replaced and leading dots are removed, so that it
+/// can't point outside of the problem's directory or hide the file.
+fn path_safe(name: &str) -> String {
+    let s: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let s = s
+        .trim_start_matches(|c: char| c == '.' || c.is_whitespace())
+        .trim_end();
+    if s.is_empty() {
+        "_".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+/// Create the directory for a single problem containing its solution file
+/// (rendered from the template) and a pair of input/expected output files for
+/// each testcase. Existing solution files are never overwritten.
+pub fn setup_problem(
+    root: &Path,
+    contest_id: i64,
+    problem: &CFProblem,
+    testcases: &[Testcase],
+    settings: &WorkspaceSettings,
+    template: Option<&str>,
+) -> Result<(), String> {
+    let vars = problem_vars(contest_id, problem);
+    let vars: Vec<(&str, &str)> =
+        vars.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    let name = path_safe(&problem.name);
+    let path_vars: Vec<(&str, &str)> = vars
+        .iter()
+        .map(|&(k, v)| {
+            if k == "name" {
+                (k, name.as_str())
+            } else {
+                (k, v)
+            }
+        })
+        .collect();
+
+    let dir = root.join(render(&settings.problem_dir, &path_vars));
+    if let Err(e) = DirBuilder::new().recursive(true).create(&dir) {
+        return Err(format!("unable to create {}: {}", dir.display(), e));
+    }
+
+    let solution = dir.join(render(&settings.solution_filename, &path_vars));
+    if solution.exists() {
+        eprintln!("{} already exists, not overwriting", solution.display());
+    } else {
+        let src = template.map(|t| render(t, &vars)).unwrap_or_default();
+        if let Err(e) = fs::write(&solution, src) {
+            return Err(format!(
+                "unable to write {}: {}",
+                solution.display(),
+                e
+            ));
+        }
+        eprintln!("created {}", solution.display());
+    }
+
+    for (n, t) in testcases.iter().enumerate() {
+        let num = (n + 1).to_string();
+        let mut vars = path_vars.clone();
+        vars.push(("num", &num));
+        let files = [
+            (&settings.input_filename, &t.input),
+            (&settings.output_filename, &t.output),
+        ];
+        for (pattern, contents) in files.iter() {
+            let path = dir.join(render(pattern, &vars));
+            if let Err(e) = fs::write(&path, format!("{}\n", contents)) {
+                return Err(format!(
+                    "unable to write {}: {}",
+                    path.display(),
+                    e
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Stored in the root of a workspace by `caffeine contest setup`, so that the
+/// contest can be inferred from the path of solutions in the workspace.
+#[derive(Serialize, Deserialize)]
+struct WorkspaceInfo {
+    /// The contest as given to `contest setup` (a contest id or url).
+    contest: String,
+}
+
+pub fn write_info(root: &Path, contest: &str) -> Result<(), String> {
+    let info = WorkspaceInfo {
+        contest: contest.to_string(),
+    };
+    // unwrap is probably ok here since serializing errors are very rare
+    let s = serde_yaml::to_string(&info).unwrap();
+    let path = root.join(crate::WORKSPACE_FILE_NAME);
+    fs::write(&path, s)
+        .map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+fn read_info(dir: &Path) -> Option<ProblemId> {
+    let s = fs::read_to_string(dir.join(crate::WORKSPACE_FILE_NAME)).ok()?;
+    let info: WorkspaceInfo = serde_yaml::from_str(&s).ok()?;
+    info.contest.parse().ok()
+}
+
+/// Infer the problem a solution file is for from its path. The file's name may
+/// be a problem code (eg. `1466b.cpp`), else the problem index is taken from
+/// the file's name (eg. `1466/b.cpp`) or its directory (eg. `1466/b/main.cpp`)
+/// and the contest from the closest workspace created by `contest setup` or
+/// directory named after a contestId.
+pub fn problem_from_path(file: &Path) -> Option<ProblemId> {
+    let stem = file.file_stem()?.to_str()?;
+    if let Ok(id) = stem.parse::<ProblemId>() {
+        if id.index.is_some() {
+            return Some(id);
+        }
+    }
+    let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    let dir_name =
+        |d: &Path| d.file_name().and_then(|n| n.to_str()).map(String::from);
+    let index = target::parse_index(stem).or_else(|| {
+        file.parent()
+            .and_then(dir_name)
+            .and_then(|n| target::parse_index(&n))
+    })?;
+    file.ancestors().skip(1).find_map(|dir| {
+        let id = read_info(dir).or_else(|| {
+            dir_name(dir)
+                .and_then(|n| n.parse::<ProblemId>().ok())
+                .filter(|id| id.index.is_none())
+        })?;
+        Some(ProblemId {
+            target: id.target,
+            index: Some(index.clone()),
+        })
+    })
+}
+
+/// Files in `dir` and its subdirectories (up to `depth` levels deep), hidden
+/// entries are skipped.
+fn files_in(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(rd) => rd.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for path in entries {
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'));
+        if hidden {
+            continue;
+        } else if path.is_dir() {
+            if depth > 0 {
+                files_in(&path, depth - 1, files);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// Whether `src` matches the parts of a template split at `<name>`, where the
+/// name may be any text within a line.
+fn matches_parts(src: &str, parts: &[String]) -> bool {
+    match parts.split_first() {
+        None => src.is_empty(),
+        Some((part, rest)) => src.match_indices(part.as_str()).any(|(i, _)| {
+            !src[..i].contains('\n')
+                && matches_parts(&src[i + part.len()..], rest)
+        }),
+    }
+}
+
+/// Whether `src` is `template` rendered with `vars`. The name of the problem
+/// isn't known without fetching it, so `<name>` matches any text within a
+/// line.
+fn is_unchanged_template(
+    template: &str,
+    vars: &[(&str, &str)],
+    src: &str,
+) -> bool {
+    let parts: Vec<String> =
+        template.split("<name>").map(|p| render(p, vars)).collect();
+    // split always returns at least one part
+    let (first, rest) = parts.split_first().unwrap();
+    src.strip_prefix(first.as_str())
+        .is_some_and(|src| matches_parts(src, rest))
+}
+
+/// Solutions in the workspace at `root`: files with the extension of
+/// `solution_filename` (or one of `extensions`) whose problem can be inferred
+/// from their path. Files which are empty or unchanged from the template are
+/// skipped since those problems haven't been solved yet.
+pub fn find_solutions(
+    root: &Path,
+    settings: &WorkspaceSettings,
+    extensions: &[String],
+) -> Result<Vec<(ProblemId, PathBuf)>, String> {
+    let template = settings.read_template()?;
+    let ext_of = |p: &Path| {
+        p.extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase)
+    };
+    let mut exts: Vec<String> =
+        extensions.iter().map(|e| e.to_lowercase()).collect();
+    exts.extend(ext_of(Path::new(&settings.solution_filename)));
+
+    let mut files = Vec::new();
+    files_in(root, 2, &mut files);
+    let mut solutions = Vec::new();
+    for file in files {
+        if !ext_of(&file).is_some_and(|e| exts.contains(&e)) {
+            continue;
+        }
+        let id = match problem_from_path(&file) {
+            Some(id) => id,
+            None => continue,
+        };
+        let src = match fs::read_to_string(&file) {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(format!("unable to read {}: {}", file.display(), e))
+            }
+        };
+        // unwrap is fine since inferred problems always have an index
+        let index = id.index.clone().unwrap();
+        let contest_id = id.contest_id().to_string();
+        let vars = [
+            ("contestid", contest_id.as_str()),
+            ("problem", &index.to_lowercase()),
+            ("PROBLEM", &index),
+        ];
+        if src.trim().is_empty() {
+            eprintln!("skipping {} (empty)", file.display());
+        } else if template
+            .as_ref()
+            .is_some_and(|t| is_unchanged_template(t, &vars, &src))
+        {
+            eprintln!("skipping {} (unchanged from template)", file.display());
+        } else {
+            solutions.push((id, file));
+        }
+    }
+    Ok(solutions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VARS: &[(&str, &str)] =
+        &[("contestid", "1466"), ("problem", "b"), ("PROBLEM", "B")];
+
+    #[test]
+    fn unchanged_template_without_name() {
+        let t = "// <contestid><PROBLEM>\nint main() {}\n";
+        assert!(is_unchanged_template(t, VARS, "// 1466B\nint main() {}\n"));
+        assert!(!is_unchanged_template(
+            t,
+            VARS,
+            "// 1466B\nint main() {\n}\n"
+        ));
+        assert!(!is_unchanged_template(t, VARS, "// 1466C\nint main() {}\n"));
+    }
+
+    #[test]
+    fn unchanged_template_with_name() {
+        let t = "// <contestid><PROBLEM>: <name>\nint main() {}\n";
+        let src = "// 1466B: Last minute enhancements\nint main() {}\n";
+        assert!(is_unchanged_template(t, VARS, src));
+        let solved = "// 1466B: Last minute enhancements\nint main() {\n  \
+            return 0;\n}\n";
+        assert!(!is_unchanged_template(t, VARS, solved));
+        // the name can't span lines
+        let t = "// <name>\n";
+        assert!(is_unchanged_template(t, VARS, "// a b: c\n"));
+        assert!(!is_unchanged_template(t, VARS, "// a\nint main() {}\n"));
+        // names repeated in the template
+        let t = "<name>\n/* <name> */\n";
+        assert!(is_unchanged_template(t, VARS, "A: B\n/* A: B */\n"));
+        assert!(!is_unchanged_template(t, VARS, "A: B\n/* A: B */\nmain\n"));
+    }
+
+    #[test]
+    fn path_safe_names() {
+        for (name, expected) in [
+            ("Last minute enhancements", "Last minute enhancements"),
+            ("A/B Testing", "A_B Testing"),
+            ("..\\..\\x", "_.._x"),
+            ("../../etc", "_.._etc"),
+            (".hidden", "hidden"),
+            (" . . x. ", "x."),
+            ("tab\there", "tab_here"),
+            ("..", "_"),
+            ("", "_"),
+        ] {
+            assert_eq!(path_safe(name), expected, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn names_stay_within_the_workspace() {
+        let _dirs = crate::testutil::isolated_dirs();
+        let root = crate::testutil::proj_dirs().cache_dir().join("ws");
+        let problem: CFProblem = serde_json::from_value(serde_json::json!({
+            "contestId": 1466,
+            "index": "B",
+            "name": "../../A/B",
+            "type": "PROGRAMMING",
+            "tags": [],
+        }))
+        .unwrap();
+        let settings = WorkspaceSettings {
+            problem_dir: "<name>".into(),
+            solution_filename: "<name>.cpp".into(),
+            input_filename: "<name><num>.in".into(),
+            output_filename: "<name><num>.out".into(),
+            checker_filename: DEFAULT_CHECKER_FILENAME.into(),
+            template: None,
+        };
+        let testcases = [Testcase {
+            input: "1".into(),
+            output: "2".into(),
+        }];
+        let template = "// <name>\n";
+        setup_problem(
+            &root,
+            1466,
+            &problem,
+            &testcases,
+            &settings,
+            Some(template),
+        )
+        .unwrap();
+        let dir = root.join("_.._A_B");
+        let src = fs::read_to_string(dir.join("_.._A_B.cpp")).unwrap();
+        assert_eq!(src, "// ../../A/B\n");
+        assert_eq!(fs::read_to_string(dir.join("_.._A_B1.in")).unwrap(), "1\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("_.._A_B1.out")).unwrap(),
+            "2\n"
+        );
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 1);
+    }
+}